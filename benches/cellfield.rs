@@ -0,0 +1,213 @@
+// cargo bench --features bench
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use macroquad::rand;
+use macroquad_forestfire::{CellField, SimConfig, SimState};
+use std::hint::black_box;
+
+const W: usize = 1920;
+const H: usize = 1080;
+
+// the one-bool-per-cell layout the packed field replaced, as a baseline
+struct VecBoolField {
+    arr: Vec<bool>,
+    w: usize,
+}
+
+impl VecBoolField {
+    fn new(w: usize, h: usize) -> VecBoolField {
+        VecBoolField {
+            arr: vec![false; w * h],
+            w,
+        }
+    }
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.arr[y * self.w + x]
+    }
+    fn set(&mut self, x: usize, y: usize) {
+        self.arr[y * self.w + x] = true;
+    }
+    fn clr(&mut self, x: usize, y: usize) {
+        self.arr[y * self.w + x] = false;
+    }
+    fn count_set(&self) -> usize {
+        self.arr.iter().filter(|b| **b).count()
+    }
+}
+
+fn coords(n: usize) -> Vec<(usize, usize)> {
+    rand::srand(42);
+    (0..n)
+        .map(|_| (rand::gen_range(0, W), rand::gen_range(0, H)))
+        .collect()
+}
+
+// each cell set with even odds, in both layouts
+fn half_full() -> (CellField, VecBoolField) {
+    rand::srand(42);
+    let mut cf = CellField::new(W, H);
+    let mut vb = VecBoolField::new(W, H);
+    for y in 0..H {
+        for x in 0..W {
+            if rand::gen_range(0, 2) == 1 {
+                cf.set(x, y);
+                vb.set(x, y);
+            }
+        }
+    }
+    (cf, vb)
+}
+
+fn access(c: &mut Criterion) {
+    let pts = coords(1 << 16);
+    let (mut cf, mut vb) = half_full();
+    let mut g = c.benchmark_group("access");
+    g.throughput(Throughput::Elements(pts.len() as u64));
+    g.bench_function("get/cellfield", |b| {
+        b.iter(|| pts.iter().filter(|&&(x, y)| cf.get(x, y)).count())
+    });
+    g.bench_function("get/vecbool", |b| {
+        b.iter(|| pts.iter().filter(|&&(x, y)| vb.get(x, y)).count())
+    });
+    g.bench_function("set/cellfield", |b| {
+        b.iter(|| pts.iter().for_each(|&(x, y)| cf.set(x, y)))
+    });
+    g.bench_function("set/vecbool", |b| {
+        b.iter(|| pts.iter().for_each(|&(x, y)| vb.set(x, y)))
+    });
+    g.bench_function("clr/cellfield", |b| {
+        b.iter(|| pts.iter().for_each(|&(x, y)| cf.clr(x, y)))
+    });
+    g.bench_function("clr/vecbool", |b| {
+        b.iter(|| pts.iter().for_each(|&(x, y)| vb.clr(x, y)))
+    });
+    g.finish();
+}
+
+fn count(c: &mut Criterion) {
+    let (cf, vb) = half_full();
+    let mut g = c.benchmark_group("count_set");
+    g.throughput(Throughput::Elements((W * H) as u64));
+    g.bench_function("cellfield", |b| b.iter(|| black_box(&cf).count_set()));
+    g.bench_function("vecbool", |b| b.iter(|| black_box(&vb).count_set()));
+    g.finish();
+}
+
+// iter_set skips empty words and walks the set bits, against testing every cell
+fn iter(c: &mut Criterion) {
+    let (cf, _) = half_full();
+    let mut g = c.benchmark_group("iter_set");
+    g.throughput(Throughput::Elements((W * H) as u64));
+    g.bench_function("iter_set", |b| {
+        b.iter(|| black_box(&cf).iter_set().fold(0, |a, (x, y)| a ^ x ^ y))
+    });
+    g.bench_function("get", |b| {
+        b.iter(|| {
+            let cf = black_box(&cf);
+            let mut a = 0;
+            for y in 0..H {
+                for x in 0..W {
+                    if cf.get(x, y) {
+                        a ^= x ^ y;
+                    }
+                }
+            }
+            a
+        })
+    });
+    g.finish();
+}
+
+// fill_rect goes a word at a time, against setting the 500x500 cells one by one
+fn rect(c: &mut Criterion) {
+    let mut cf = CellField::new(W, H);
+    let mut g = c.benchmark_group("fill_rect");
+    g.throughput(Throughput::Elements(500 * 500));
+    g.bench_function("words", |b| {
+        b.iter(|| cf.fill_rect(black_box(101), 37, 600, 536))
+    });
+    g.bench_function("cells", |b| {
+        b.iter(|| {
+            for y in 37..=536 {
+                for x in black_box(101)..=600 {
+                    cf.set(x, y);
+                }
+            }
+        })
+    });
+    g.finish();
+}
+
+// 4-connected patches of a forest at half density, near the percolation threshold
+fn components(c: &mut Criterion) {
+    let (cf, _) = half_full();
+    let mut g = c.benchmark_group("components");
+    g.sample_size(10);
+    g.throughput(Throughput::Elements((W * H) as u64));
+    g.bench_function("largest", |b| b.iter(|| cf.largest_component_size()));
+    g.finish();
+}
+
+fn step(c: &mut Criterion) {
+    let mut g = c.benchmark_group("step");
+    g.sample_size(10);
+    for (w, h) in [(640, 480), (1920, 1080), (3840, 2160)] {
+        rand::srand(42);
+        let mut sim = SimState::new(SimConfig::new(), w, h);
+        g.throughput(Throughput::Elements((w * h) as u64));
+        g.bench_function(BenchmarkId::from_parameter(format!("{w}x{h}")), |b| {
+            b.iter(|| sim.step())
+        });
+    }
+    g.finish();
+}
+
+// the spread of 100k fires, compare against a build with --features bench,parallel
+fn propagate(c: &mut Criterion) {
+    rand::srand(42);
+    let mut sim = SimState::new(SimConfig::new(), W, H);
+    sim.light_fires(100_000);
+    let mut g = c.benchmark_group("propagate");
+    g.sample_size(20);
+    g.throughput(Throughput::Elements(100_000));
+    let name = match cfg!(feature = "parallel") {
+        false => "serial",
+        true => "parallel",
+    };
+    g.bench_function(name, |b| b.iter(|| sim.propagate(black_box(7))));
+    g.finish();
+}
+
+// a rendered frame of n steps and one image upload, throughput in steps. There
+// is no GL context here, so copying the image bytes stands in for texture.update
+fn steps_per_frame(c: &mut Criterion) {
+    let mut g = c.benchmark_group("steps_per_frame");
+    g.sample_size(10);
+    for n in [1, 100] {
+        rand::srand(42);
+        let mut sim = SimState::new(SimConfig::new(), W, H);
+        let mut upload = vec![0u8; W * H * 4];
+        g.throughput(Throughput::Elements(n as u64));
+        g.bench_function(BenchmarkId::from_parameter(n), |b| {
+            b.iter(|| {
+                for _ in 0..n {
+                    sim.step();
+                }
+                upload.copy_from_slice(&sim.image().bytes);
+            })
+        });
+    }
+    g.finish();
+}
+
+criterion_group!(
+    benches,
+    access,
+    count,
+    iter,
+    rect,
+    components,
+    propagate,
+    step,
+    steps_per_frame
+);
+criterion_main!(benches);
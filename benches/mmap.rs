@@ -0,0 +1,49 @@
+// cargo bench --features bench,mmap-state
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use macroquad::rand;
+use macroquad_forestfire::CellField;
+
+const N: usize = 4096;
+
+fn coords(n: usize) -> Vec<(usize, usize)> {
+    rand::srand(42);
+    (0..n)
+        .map(|_| (rand::gen_range(0, N), rand::gen_range(0, N)))
+        .collect()
+}
+
+// the same random cells read and written in a 4K x 4K field on the heap and
+// in one mapped from a file
+fn mmap(c: &mut Criterion) {
+    let pts = coords(1 << 16);
+    let path = std::env::temp_dir().join(format!("forestfire-bench-{}.bin", std::process::id()));
+    let mut fields = [
+        ("heap", CellField::new(N, N)),
+        (
+            "mmap",
+            CellField::new_mmap(N, N, path.to_str().unwrap()).unwrap(),
+        ),
+    ];
+    let mut g = c.benchmark_group("mmap");
+    g.throughput(Throughput::Elements(pts.len() as u64));
+    for (name, cf) in &mut fields {
+        for &(x, y) in pts.iter().step_by(2) {
+            cf.set(x, y);
+        }
+        g.bench_function(format!("read/{name}"), |b| {
+            b.iter(|| pts.iter().filter(|&&(x, y)| cf.get(x, y)).count())
+        });
+        g.bench_function(format!("write/{name}"), |b| {
+            b.iter(|| {
+                pts.iter().for_each(|&(x, y)| cf.set(x, y));
+                pts.iter().for_each(|&(x, y)| cf.clr(x, y));
+            })
+        });
+    }
+    g.finish();
+    drop(fields);
+    let _ = std::fs::remove_file(path);
+}
+
+criterion_group!(benches, mmap);
+criterion_main!(benches);
@@ -0,0 +1,97 @@
+#[cfg(feature = "audio")]
+use macroquad::audio::{
+    load_sound_from_bytes, play_sound, set_sound_volume, PlaySoundParams, Sound,
+};
+
+#[cfg(feature = "audio")]
+const RATE: u32 = 22050;
+
+// a second of sparse decaying clicks as a 16 bit mono wav, looped it crackles
+#[cfg(feature = "audio")]
+fn crackle_wav() -> Vec<u8> {
+    let mut seed: u32 = 0x2545_f491;
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed
+    };
+    let mut amp = 0f32;
+    let samples: Vec<i16> = (0..RATE)
+        .map(|_| {
+            if next() % 400 == 0 {
+                amp = 0.3 + (next() % 1000) as f32 / 1500.;
+            }
+            amp *= 0.995;
+            let noise = (next() % 2001) as f32 / 1000. - 1.;
+            (noise * amp * i16::MAX as f32) as i16
+        })
+        .collect();
+    let data_len = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    // pcm, one channel
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&RATE.to_le_bytes());
+    wav.extend_from_slice(&(RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    wav.extend(samples.iter().flat_map(|s| s.to_le_bytes()));
+    wav
+}
+
+// the crackle of the fires, louder the more cells burn
+#[cfg(feature = "audio")]
+pub struct FireSound {
+    sound: Option<Sound>,
+    volume: f32,
+}
+
+#[cfg(feature = "audio")]
+impl FireSound {
+    pub async fn new() -> FireSound {
+        let sound = load_sound_from_bytes(&crackle_wav())
+            .await
+            .map_err(|e| macroquad::logging::warn!("could not load the fire sound: {}", e))
+            .ok();
+        if let Some(s) = sound {
+            play_sound(
+                s,
+                PlaySoundParams {
+                    looped: true,
+                    volume: 0.,
+                },
+            );
+        }
+        FireSound { sound, volume: 0. }
+    }
+    // full volume once 2% of the cells burn, only changes of a percent are sent
+    pub fn update(&mut self, fires: usize, cells: usize) {
+        let Some(s) = self.sound else {
+            return;
+        };
+        let volume = (fires as f32 / cells.max(1) as f32 * 50.).min(1.);
+        if (volume - self.volume).abs() >= 0.01 || (volume == 0. && self.volume > 0.) {
+            set_sound_volume(s, volume);
+            self.volume = volume;
+        }
+    }
+}
+
+// without audio the fires are silent
+#[cfg(not(feature = "audio"))]
+pub struct FireSound;
+
+#[cfg(not(feature = "audio"))]
+impl FireSound {
+    pub async fn new() -> FireSound {
+        FireSound
+    }
+    pub fn update(&mut self, _fires: usize, _cells: usize) {}
+}
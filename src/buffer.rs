@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+// items for the next step are written to back while front stays readable, a
+// reader holding the Arc from swap keeps its step even after the next swap
+pub struct DoubleBuffer<T> {
+    front: Arc<Vec<T>>,
+    back: Vec<T>,
+}
+
+impl<T: Clone> DoubleBuffer<T> {
+    pub fn new() -> DoubleBuffer<T> {
+        DoubleBuffer {
+            front: Arc::new(Vec::new()),
+            back: Vec::new(),
+        }
+    }
+    pub fn write_back(&mut self, item: T) {
+        self.back.push(item);
+    }
+    pub fn reserve_back(&mut self, n: usize) {
+        self.back.reserve(n);
+    }
+    // back becomes front, the old front is cleared and reused as back unless a
+    // reader still holds it
+    pub fn swap(&mut self) -> Arc<Vec<T>> {
+        let back = std::mem::take(&mut self.back);
+        let old = std::mem::replace(&mut self.front, Arc::new(back));
+        if let Ok(mut v) = Arc::try_unwrap(old) {
+            v.clear();
+            self.back = v;
+        }
+        Arc::clone(&self.front)
+    }
+    pub fn read_front(&self) -> &[T] {
+        &self.front
+    }
+    // the front to read while writing back
+    pub fn share_front(&self) -> Arc<Vec<T>> {
+        Arc::clone(&self.front)
+    }
+    // for edits between steps, copies the front first if a reader holds it
+    pub fn front_mut(&mut self) -> &mut Vec<T> {
+        Arc::make_mut(&mut self.front)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_moves_back_to_front() {
+        let mut buf = DoubleBuffer::new();
+        for i in 0..1000 {
+            buf.write_back(i);
+        }
+        assert!(buf.read_front().is_empty());
+        let held = buf.swap();
+        assert_eq!(buf.read_front().len(), 1000);
+        assert_eq!(buf.read_front()[999], 999);
+        // a reader keeps its step across the next swap
+        buf.write_back(7);
+        buf.swap();
+        assert_eq!(held.len(), 1000);
+        assert_eq!(buf.read_front(), &[7]);
+    }
+}
@@ -0,0 +1,6666 @@
+use macroquad::prelude::*;
+
+mod audio;
+mod buffer;
+mod mmap;
+mod noise;
+mod recorder;
+mod replay;
+mod shader;
+mod state;
+mod undo;
+
+use audio::FireSound;
+use buffer::DoubleBuffer;
+use macroquad::ui::{hash, root_ui, widgets, Skin};
+use mmap::MmapVec;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use recorder::{GifRecorder, NpyWriter, PngSaver, StatsRecorder};
+use replay::ReplayMode;
+use serde::{Deserialize, Serialize};
+use shader::ShaderRenderer;
+use state::Snapshot;
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::process::exit;
+use std::rc::Rc;
+use std::sync::LazyLock;
+use undo::{UndoOp, UndoStack};
+
+// on after a press and release, off after the next one. A press counts once the
+// key is held hold_frames frames in a row, a release after release_frames
+struct DebounceToggle<F: Fn() -> bool> {
+    f: F,
+    state: usize,
+    frames: usize,
+    hold_frames: usize,
+    release_frames: usize,
+}
+
+impl<F: Fn() -> bool> DebounceToggle<F> {
+    fn new(f: F) -> DebounceToggle<F> {
+        DebounceToggle::with_timing(f, 1, 1)
+    }
+    fn with_timing(f: F, hold_frames: usize, release_frames: usize) -> DebounceToggle<F> {
+        DebounceToggle {
+            f,
+            state: 0,
+            frames: 0,
+            hold_frames: hold_frames.max(1),
+            release_frames: release_frames.max(1),
+        }
+    }
+    fn get(&mut self) -> bool {
+        // states 0 and 2 wait for a press, 1 and 3 for a release
+        let down = (self.f)();
+        let (waiting, needed) = match self.state {
+            0 | 2 => (down, self.hold_frames),
+            _ => (!down, self.release_frames),
+        };
+        self.frames = match waiting {
+            false => 0,
+            true => self.frames + 1,
+        };
+        if self.frames >= needed {
+            self.state = (self.state + 1) % 4;
+            self.frames = 0;
+        }
+
+        self.state == 2
+    }
+    // back to off and waiting for a press
+    fn reset(&mut self) {
+        self.state = 0;
+        self.frames = 0;
+    }
+}
+
+struct PoissonProcess(f32);
+
+impl PoissonProcess {
+    fn new() -> PoissonProcess {
+        PoissonProcess(0.0)
+    }
+    fn draw(&mut self, avgper: f32) -> usize {
+        let PoissonProcess(ref mut acc) = self;
+
+        let ur = ((1.0 + rand::rand() as f64) / u32::MAX as f64) as f32;
+        let er = -avgper * ur.ln();
+        let newacc = *acc + er;
+        let faf = newacc.floor();
+        *acc = newacc - faf;
+        faf as usize
+    }
+}
+
+// walker's alias method, one index in proportion to its weight per two draws
+struct AliasTable {
+    prob: Vec<f32>,
+    alias: Vec<usize>,
+}
+
+impl AliasTable {
+    fn new(weights: &[f32]) -> AliasTable {
+        let n = weights.len();
+        let total: f64 = weights.iter().map(|&w| w as f64).sum();
+        let mut scaled: Vec<f64> = weights
+            .iter()
+            .map(|&w| w as f64 * n as f64 / total)
+            .collect();
+        let mut prob = vec![1.0; n];
+        let mut alias: Vec<usize> = (0..n).collect();
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) =
+            (0..n).partition(|&i| scaled[i] < 1.);
+        while let (Some(s), Some(&l)) = (small.pop(), large.last()) {
+            prob[s] = scaled[s] as f32;
+            alias[s] = l;
+            scaled[l] -= 1. - scaled[s];
+            if scaled[l] < 1. {
+                large.pop();
+                small.push(l);
+            }
+        }
+        AliasTable { prob, alias }
+    }
+    fn sample(&self) -> usize {
+        let i = rand_range_usize(0, self.prob.len());
+        match rand::gen_range(0.0f32, 1.0) < self.prob[i] {
+            false => self.alias[i],
+            true => i,
+        }
+    }
+}
+
+// spontaneous fires with a per cell rate multiplier, uniform by default
+struct PoissonProcess2D {
+    rate_field: Vec<f32>,
+    w: usize,
+    h: usize,
+    // mean of rate_field
+    norm: f32,
+    acc: PoissonProcess,
+    // None for a uniform field, sampled the cheaper way
+    table: Option<AliasTable>,
+}
+
+impl PoissonProcess2D {
+    fn uniform(w: usize, h: usize) -> PoissonProcess2D {
+        PoissonProcess2D {
+            rate_field: vec![1.; w * h],
+            w,
+            h,
+            norm: 1.,
+            acc: PoissonProcess::new(),
+            table: None,
+        }
+    }
+    fn from_rates(rate_field: Vec<f32>, w: usize, h: usize) -> PoissonProcess2D {
+        let sum: f64 = rate_field.iter().map(|&r| r as f64).sum();
+        let norm = (sum / rate_field.len().max(1) as f64) as f32;
+        let table = match norm > 0. && rate_field.iter().any(|&r| r != rate_field[0]) {
+            false => None,
+            true => Some(AliasTable::new(&rate_field)),
+        };
+        PoissonProcess2D {
+            rate_field,
+            w,
+            h,
+            norm,
+            acc: PoissonProcess::new(),
+            table,
+        }
+    }
+    fn resize(&mut self, w: usize, h: usize) {
+        let rates = resize_grid(&self.rate_field, self.w, w, h);
+        let acc = self.acc.0;
+        *self = PoissonProcess2D::from_rates(rates, w, h);
+        self.acc.0 = acc;
+    }
+    // cells to ignite this step, base_rate per cell of multiplier 1
+    fn draw(&mut self, base_rate: f32) -> Vec<(usize, usize)> {
+        let n = self
+            .acc
+            .draw(base_rate * self.norm * (self.w * self.h) as f32);
+        (0..n)
+            .map(|_| match &self.table {
+                None => (rand_range_usize(0, self.w), rand_range_usize(0, self.h)),
+                Some(t) => {
+                    let i = t.sample();
+                    (i % self.w, i / self.w)
+                }
+            })
+            .collect()
+    }
+}
+
+fn rand_range_usize(low: usize, high: usize) -> usize {
+    let r = rand::rand() as f64 / (u32::MAX as f64 + 1f64);
+    low + (r * (high - low) as f64).floor() as usize
+}
+
+// pair of independent standard normal samples via box-muller
+fn gaussian_pair() -> (f32, f32) {
+    let u1 = ((1.0 + rand::rand() as f64) / (u32::MAX as f64 + 1f64)) as f32;
+    let u2 = rand::gen_range(0.0f32, 1.0);
+    let r = (-2.0 * u1.ln()).sqrt();
+    let t = std::f32::consts::TAU * u2;
+    (r * t.cos(), r * t.sin())
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+// sets every tree within r of (cx, cy) on fire
+fn ignite_cluster(fires: &mut Vec<Fire>, cf: &mut CellField, cx: usize, cy: usize, r: usize) {
+    let (w, h) = (cf.w, cf.h);
+    for_disk(cx, cy, r, w, h, |x, y| {
+        if cf.get(x, y) {
+            cf.clr(x, y);
+            fires.push(Fire::new(x, y, 1.0));
+        }
+    });
+}
+
+// visit all cells of a w x h grid within distance r of (cx, cy)
+fn for_disk(cx: usize, cy: usize, r: usize, w: usize, h: usize, mut f: impl FnMut(usize, usize)) {
+    let (x0, x1) = (cx.saturating_sub(r), (cx + r).min(w - 1));
+    let (y0, y1) = (cy.saturating_sub(r), (cy + r).min(h - 1));
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            let (dx, dy) = (x.abs_diff(cx), y.abs_diff(cy));
+            if dx * dx + dy * dy <= r * r {
+                f(x, y);
+            }
+        }
+    }
+}
+
+// bresenham line from (x0, y0) to (x1, y1), both ends included
+fn for_line(x0: usize, y0: usize, x1: usize, y1: usize, mut f: impl FnMut(usize, usize)) {
+    let (mut x, mut y) = (x0 as i64, y0 as i64);
+    let (x1, y1) = (x1 as i64, y1 as i64);
+    let dx = (x1 - x).abs();
+    let dy = -(y1 - y).abs();
+    let sx = if x < x1 { 1 } else { -1 };
+    let sy = if y < y1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    loop {
+        f(x as usize, y as usize);
+        if x == x1 && y == y1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Fire {
+    x: usize,
+    y: usize,
+    age: usize,
+    intensity: f32,
+}
+
+impl Fire {
+    fn new(x: usize, y: usize, intensity: f32) -> Fire {
+        Fire {
+            x,
+            y,
+            age: 0,
+            intensity,
+        }
+    }
+}
+
+const MAX_EMBERS: usize = 500;
+const MAX_EVENTS: usize = 200;
+
+// the fires burning now in front, the next step's are written to back
+type FireBuffer = DoubleBuffer<Fire>;
+
+// at most one fire per cell, later ones for the same cell are dropped
+fn push_fire(fires: &mut FireBuffer, fire_field: &mut CellField, fire: Fire) {
+    if fire_field.get(fire.x, fire.y) {
+        return;
+    }
+    fire_field.set(fire.x, fire.y);
+    fires.write_back(fire);
+}
+
+// a dry spell with remaining steps left, started at random by step
+struct DroughtState {
+    active: bool,
+    remaining: usize,
+}
+
+impl DroughtState {
+    fn new() -> DroughtState {
+        DroughtState {
+            active: false,
+            remaining: 0,
+        }
+    }
+}
+
+// logged together with the step it happened in
+#[derive(Clone, Debug, Serialize)]
+enum SimEvent {
+    // lightning at (x, y), with the number of fires already burning
+    FireIgnited(usize, usize, usize),
+    // the last fire went out, or one was put out by hand, at (x, y)
+    FireExtinguished(usize, usize),
+    RainEvent,
+    // lightning is drought_multiplier log units likelier until the end
+    DroughtStart,
+    DroughtEnd,
+    // density moved into another band around the percolation threshold
+    DensityAlert(f32),
+}
+
+impl SimEvent {
+    fn csv_row(&self, frame: usize) -> String {
+        match self {
+            SimEvent::FireIgnited(x, y, _) => format!("{},FireIgnited,{},{}", frame, x, y),
+            SimEvent::FireExtinguished(x, y) => format!("{},FireExtinguished,{},{}", frame, x, y),
+            SimEvent::RainEvent => format!("{},RainEvent,,", frame),
+            SimEvent::DroughtStart => format!("{},DroughtStart,,", frame),
+            SimEvent::DroughtEnd => format!("{},DroughtEnd,,", frame),
+            SimEvent::DensityAlert(_) => format!("{},DensityAlert,,", frame),
+        }
+    }
+}
+
+fn log_event(log: &mut VecDeque<(usize, SimEvent)>, frame: usize, event: SimEvent) {
+    if log.len() == MAX_EVENTS {
+        log.pop_front();
+    }
+    log.push_back((frame, event));
+}
+
+fn events_csv(log: &VecDeque<(usize, SimEvent)>) -> String {
+    let mut csv = String::from("frame,type,x,y\n");
+    for (frame, event) in log {
+        csv.push_str(&event.csv_row(*frame));
+        csv.push('\n');
+    }
+    csv
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "state::CellFieldData", try_from = "state::CellFieldData")]
+pub struct CellField {
+    arr: MmapVec,
+    ystride: usize,
+    w: usize,
+    h: usize,
+}
+
+impl CellField {
+    pub fn new(w: usize, h: usize) -> CellField {
+        let nx = w.div_ceil(8);
+        let ny = h.div_ceil(8);
+        CellField {
+            arr: vec![0; nx * ny].into(),
+            ystride: nx,
+            w,
+            h,
+        }
+    }
+    // a field kept in the file at path, which holds the cells it had when the
+    // last run with the same size ended
+    #[cfg(feature = "mmap-state")]
+    pub fn new_mmap(w: usize, h: usize, path: &str) -> Result<CellField, String> {
+        let cf = CellField::new(w, h);
+        let arr = MmapVec::map_file(path, w, h, cf.arr.len()).map_err(|e| e.to_string())?;
+        Ok(CellField { arr, ..cf })
+    }
+    #[cfg(not(feature = "mmap-state"))]
+    fn new_mmap(_w: usize, _h: usize, _path: &str) -> Result<CellField, String> {
+        Err("built without the mmap-state feature".to_string())
+    }
+    // takes over the cells of cf, copied into the mapped file when this field
+    // has one of the same size
+    fn replace(&mut self, cf: CellField) {
+        match self.arr.is_mapped() && (self.w, self.h) == (cf.w, cf.h) {
+            false => *self = cf,
+            true => self.arr.copy_from_slice(&cf.arr),
+        }
+    }
+    // bits of word off that lie inside w x h, edge tiles are partly padding
+    fn valid_mask(&self, off: usize) -> u64 {
+        let cols = (self.w - off % self.ystride * 8).min(8);
+        let rows = (self.h - off / self.ystride * 8).min(8);
+        let row: u64 = 0xff >> (8 - cols);
+        (row * 0x0101_0101_0101_0101) & (u64::MAX >> (64 - 8 * rows))
+    }
+    pub fn count_set(&self) -> usize {
+        self.arr
+            .iter()
+            .enumerate()
+            .map(|(off, w)| (w & self.valid_mask(off)).count_ones() as usize)
+            .sum()
+    }
+    fn count_clr(&self) -> usize {
+        self.w * self.h - self.count_set()
+    }
+    fn density(&self) -> f64 {
+        self.count_set() as f64 / (self.w * self.h) as f64
+    }
+    // the w by h rectangle from (x0, y0), cut off at the edges
+    fn get_region(&self, x0: usize, y0: usize, w: usize, h: usize) -> CellField {
+        let w = w.min(self.w.saturating_sub(x0));
+        let h = h.min(self.h.saturating_sub(y0));
+        let mut region = CellField::new(w, h);
+        for y in 0..h {
+            for x in 0..w {
+                if self.get(x0 + x, y0 + y) {
+                    region.set(x, y);
+                }
+            }
+        }
+        region
+    }
+    // copies src over the cells from (dst_x, dst_y), clear cells too, whatever
+    // sticks out is dropped
+    fn blit(&mut self, src: &CellField, dst_x: usize, dst_y: usize) {
+        let w = src.w.min(self.w.saturating_sub(dst_x));
+        let h = src.h.min(self.h.saturating_sub(dst_y));
+        for y in 0..h {
+            for x in 0..w {
+                match src.get(x, y) {
+                    false => self.clr(dst_x + x, dst_y + y),
+                    true => self.set(dst_x + x, dst_y + y),
+                }
+            }
+        }
+    }
+    fn set_disk(&mut self, cx: usize, cy: usize, r: usize) {
+        for_disk(cx, cy, r, self.w, self.h, |x, y| self.set(x, y));
+    }
+    fn resize(&mut self, new_w: usize, new_h: usize) {
+        let mut resized = CellField::new(new_w, new_h);
+        for (x, y) in self.iter_set() {
+            if x < new_w && y < new_h {
+                resized.set(x, y);
+            }
+        }
+        *self = resized;
+    }
+    // sets or clears the inclusive rectangle a word at a time, tiles fully inside
+    // get a mask of u64::MAX
+    fn mask_rect(&mut self, x0: usize, y0: usize, x1: usize, y1: usize, set: bool) {
+        let (x1, y1) = (x1.min(self.w - 1), y1.min(self.h - 1));
+        for ty in y0 / 8..=y1 / 8 {
+            let lo = y0.max(ty * 8) - ty * 8;
+            let hi = y1.min(ty * 8 + 7) - ty * 8;
+            let rows = (u64::MAX >> (56 - 8 * hi)) & (u64::MAX << (8 * lo));
+            for tx in x0 / 8..=x1 / 8 {
+                let lo = x0.max(tx * 8) - tx * 8;
+                let hi = x1.min(tx * 8 + 7) - tx * 8;
+                let cols: u64 = (0xff >> (7 - hi)) & (0xff << lo);
+                let mask = (cols * 0x0101_0101_0101_0101) & rows;
+                let word = &mut self.arr[ty * self.ystride + tx];
+                match set {
+                    true => *word |= mask,
+                    false => *word &= !mask,
+                }
+            }
+        }
+    }
+    pub fn fill_rect(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        self.mask_rect(x0, y0, x1, y1, true);
+    }
+    fn clear_rect(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        self.mask_rect(x0, y0, x1, y1, false);
+    }
+    // walks the words, skipping empty ones and popping set bits lowest first
+    fn iter_bits(&self, invert: bool) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.arr.iter().enumerate().flat_map(move |(off, &word)| {
+            let (ox, oy) = (off % self.ystride * 8, off / self.ystride * 8);
+            let mut bits = match invert {
+                false => word,
+                true => !word,
+            } & self.valid_mask(off);
+            std::iter::from_fn(move || {
+                if bits == 0 {
+                    return None;
+                }
+                let s = bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                Some((ox + s % 8, oy + s / 8))
+            })
+        })
+    }
+    pub fn iter_set(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.iter_bits(false)
+    }
+    fn iter_clr(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.iter_bits(true)
+    }
+    fn indices(&self, x: usize, y: usize) -> (usize, usize) {
+        let (ox, ix) = (x / 8, x % 8);
+        let (oy, iy) = (y / 8, y % 8);
+        let s = iy * 8 + ix;
+        (oy * self.ystride + ox, s)
+    }
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        let (off, s) = self.indices(x, y);
+        (self.arr[off] & (1 << s)) != 0
+    }
+    pub fn set(&mut self, x: usize, y: usize) {
+        let (off, s) = self.indices(x, y);
+        self.arr[off] |= 1 << s;
+    }
+    pub fn clr(&mut self, x: usize, y: usize) {
+        let (off, s) = self.indices(x, y);
+        self.arr[off] &= !(1 << s);
+    }
+    // cells x-1, x, x+1 of row y as bits 0, 1, 2
+    fn row3(&self, x: usize, y: usize, wrap: bool) -> u32 {
+        let (off, s) = self.indices(x, y);
+        if (1..=6).contains(&(x % 8)) && x + 1 < self.w {
+            return ((self.arr[off] >> (s - 1)) & 7) as u32;
+        }
+        let left = match (x, wrap) {
+            (0, false) => false,
+            (0, true) => self.get(self.w - 1, y),
+            _ => self.get(x - 1, y),
+        };
+        let right = match (x + 1 < self.w, wrap) {
+            (true, _) => self.get(x + 1, y),
+            (false, true) => self.get(0, y),
+            (false, false) => false,
+        };
+        let mid = (self.arr[off] >> s) & 1 != 0;
+        left as u32 | (mid as u32) << 1 | (right as u32) << 2
+    }
+    fn neighbors(&self, x: usize, y: usize, eight: bool, wrap: bool) -> u8 {
+        let above = match (y, wrap) {
+            (0, false) => 0,
+            (0, true) => self.row3(x, self.h - 1, wrap),
+            _ => self.row3(x, y - 1, wrap),
+        };
+        let below = match (y + 1 < self.h, wrap) {
+            (true, _) => self.row3(x, y + 1, wrap),
+            (false, true) => self.row3(x, 0, wrap),
+            (false, false) => 0,
+        };
+        let (above, below) = match eight {
+            true => (above, below),
+            false => (above & 2, below & 2),
+        };
+        let row = self.row3(x, y, wrap) & 5;
+        (above.count_ones() + row.count_ones() + below.count_ones()) as u8
+    }
+    fn neighbor_count(&self, x: usize, y: usize, eight: bool) -> u8 {
+        self.neighbors(x, y, eight, false)
+    }
+    fn neighbor_count_wrapping(&self, x: usize, y: usize, eight: bool) -> u8 {
+        self.neighbors(x, y, eight, true)
+    }
+    // fraction of set cells in the (2 radius + 1) square around each cell, clipped
+    // to the grid, from a summed-area table
+    fn density_map_box(&self, radius: usize) -> Vec<f32> {
+        let (w, h) = (self.w, self.h);
+        let mut sat = vec![0u32; (w + 1) * (h + 1)];
+        for y in 0..h {
+            let mut row = 0;
+            for x in 0..w {
+                row += self.get(x, y) as u32;
+                sat[(y + 1) * (w + 1) + x + 1] = sat[y * (w + 1) + x + 1] + row;
+            }
+        }
+        let mut map = Vec::with_capacity(w * h);
+        for y in 0..h {
+            let (y0, y1) = (y.saturating_sub(radius), (y + radius + 1).min(h));
+            for x in 0..w {
+                let (x0, x1) = (x.saturating_sub(radius), (x + radius + 1).min(w));
+                let sum = sat[y1 * (w + 1) + x1] + sat[y0 * (w + 1) + x0]
+                    - sat[y0 * (w + 1) + x1]
+                    - sat[y1 * (w + 1) + x0];
+                map.push(sum as f32 / ((x1 - x0) * (y1 - y0)) as f32);
+            }
+        }
+        map
+    }
+    // the patches of 4-adjacent set cells, biggest first, each found by a
+    // breadth first search from a set cell not reached before
+    pub fn connected_components_4(&self) -> Vec<Vec<(usize, usize)>> {
+        let mut visited = CellField::new(self.w, self.h);
+        let mut queue = VecDeque::new();
+        let mut components = Vec::new();
+        for (x, y) in self.iter_set() {
+            if visited.get(x, y) {
+                continue;
+            }
+            visited.set(x, y);
+            queue.push_back((x, y));
+            let mut component = Vec::new();
+            while let Some((cx, cy)) = queue.pop_front() {
+                component.push((cx, cy));
+                let left = cx.checked_sub(1).map(|nx| (nx, cy));
+                let up = cy.checked_sub(1).map(|ny| (cx, ny));
+                let right = Some((cx + 1, cy)).filter(|_| cx + 1 < self.w);
+                let down = Some((cx, cy + 1)).filter(|_| cy + 1 < self.h);
+                for (nx, ny) in [left, up, right, down].into_iter().flatten() {
+                    if self.get(nx, ny) && !visited.get(nx, ny) {
+                        visited.set(nx, ny);
+                        queue.push_back((nx, ny));
+                    }
+                }
+            }
+            components.push(component);
+        }
+        components.sort_by_key(|c| std::cmp::Reverse(c.len()));
+        components
+    }
+    // cells in the biggest patch of 4-adjacent set cells
+    pub fn largest_component_size(&self) -> usize {
+        self.connected_components_4().first().map_or(0, Vec::len)
+    }
+}
+
+// word by word combinations of two fields of the same size
+fn zip_words(a: &CellField, b: &CellField, f: impl Fn(u64, u64) -> u64) -> CellField {
+    let mut out = a.clone();
+    zip_words_inplace(&mut out, b, f);
+    out
+}
+
+fn zip_words_inplace(a: &mut CellField, b: &CellField, f: impl Fn(u64, u64) -> u64) {
+    assert!(
+        a.w == b.w && a.h == b.h,
+        "{}x{} and {}x{} fields",
+        a.w,
+        a.h,
+        b.w,
+        b.h
+    );
+    for (x, &y) in a.arr.iter_mut().zip(b.arr.iter()) {
+        *x = f(*x, y);
+    }
+}
+
+// cells set in one field and not the other
+fn hamming_distance(a: &CellField, b: &CellField) -> usize {
+    assert!(
+        a.w == b.w && a.h == b.h,
+        "{}x{} and {}x{} fields",
+        a.w,
+        a.h,
+        b.w,
+        b.h
+    );
+    a.arr
+        .iter()
+        .zip(b.arr.iter())
+        .enumerate()
+        .map(|(off, (x, y))| ((x ^ y) & a.valid_mask(off)).count_ones() as usize)
+        .sum()
+}
+
+// cells set in both over cells set in either, two empty fields are the same
+fn jaccard_similarity(a: &CellField, b: &CellField) -> f64 {
+    let union = or(a, b).count_set();
+    match union {
+        0 => 1.,
+        _ => and(a, b).count_set() as f64 / union as f64,
+    }
+}
+
+fn and(a: &CellField, b: &CellField) -> CellField {
+    zip_words(a, b, |x, y| x & y)
+}
+
+fn or(a: &CellField, b: &CellField) -> CellField {
+    zip_words(a, b, |x, y| x | y)
+}
+
+fn xor(a: &CellField, b: &CellField) -> CellField {
+    zip_words(a, b, |x, y| x ^ y)
+}
+
+// the padding bits of edge tiles stay clear
+fn not(a: &CellField) -> CellField {
+    let mut out = a.clone();
+    for off in 0..out.arr.len() {
+        out.arr[off] = !out.arr[off] & out.valid_mask(off);
+    }
+    out
+}
+
+fn and_inplace(a: &mut CellField, b: &CellField) {
+    zip_words_inplace(a, b, |x, y| x & y);
+}
+
+fn or_inplace(a: &mut CellField, b: &CellField) {
+    zip_words_inplace(a, b, |x, y| x | y);
+}
+
+fn xor_inplace(a: &mut CellField, b: &CellField) {
+    zip_words_inplace(a, b, |x, y| x ^ y);
+}
+
+impl std::ops::BitAnd for &CellField {
+    type Output = CellField;
+    fn bitand(self, b: &CellField) -> CellField {
+        and(self, b)
+    }
+}
+
+impl std::ops::BitOr for &CellField {
+    type Output = CellField;
+    fn bitor(self, b: &CellField) -> CellField {
+        or(self, b)
+    }
+}
+
+impl std::ops::BitXor for &CellField {
+    type Output = CellField;
+    fn bitxor(self, b: &CellField) -> CellField {
+        xor(self, b)
+    }
+}
+
+impl std::ops::Not for &CellField {
+    type Output = CellField;
+    fn not(self) -> CellField {
+        not(self)
+    }
+}
+
+impl std::ops::BitAndAssign<&CellField> for CellField {
+    fn bitand_assign(&mut self, b: &CellField) {
+        and_inplace(self, b);
+    }
+}
+
+impl std::ops::BitOrAssign<&CellField> for CellField {
+    fn bitor_assign(&mut self, b: &CellField) {
+        or_inplace(self, b);
+    }
+}
+
+impl std::ops::BitXorAssign<&CellField> for CellField {
+    fn bitxor_assign(&mut self, b: &CellField) {
+        xor_inplace(self, b);
+    }
+}
+
+const SPARSE_TILE: usize = 64;
+
+// a CellField for mostly empty grids, in 64x64 tiles of one word per row. A
+// tile is allocated when one of its cells is set and dropped once all are clear
+struct SparseCellField {
+    tiles: HashMap<(usize, usize), Box<[u64; SPARSE_TILE]>>,
+    w: usize,
+    h: usize,
+}
+
+impl SparseCellField {
+    fn new(w: usize, h: usize) -> SparseCellField {
+        SparseCellField {
+            tiles: HashMap::new(),
+            w,
+            h,
+        }
+    }
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.tiles
+            .get(&(x / SPARSE_TILE, y / SPARSE_TILE))
+            .is_some_and(|t| (t[y % SPARSE_TILE] >> (x % SPARSE_TILE)) & 1 != 0)
+    }
+    fn set(&mut self, x: usize, y: usize) {
+        let t = self
+            .tiles
+            .entry((x / SPARSE_TILE, y / SPARSE_TILE))
+            .or_insert_with(|| Box::new([0; SPARSE_TILE]));
+        t[y % SPARSE_TILE] |= 1 << (x % SPARSE_TILE);
+    }
+    fn clr(&mut self, x: usize, y: usize) {
+        let key = (x / SPARSE_TILE, y / SPARSE_TILE);
+        if let Some(t) = self.tiles.get_mut(&key) {
+            t[y % SPARSE_TILE] &= !(1 << (x % SPARSE_TILE));
+            if t.iter().all(|&row| row == 0) {
+                self.tiles.remove(&key);
+            }
+        }
+    }
+    fn count_set(&self) -> usize {
+        self.tiles
+            .values()
+            .flat_map(|t| t.iter())
+            .map(|row| row.count_ones() as usize)
+            .sum()
+    }
+    // tile by tile in no particular order
+    fn iter_set(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.tiles.iter().flat_map(|(&(tx, ty), t)| {
+            t.iter().enumerate().flat_map(move |(r, &row)| {
+                let mut bits = row;
+                std::iter::from_fn(move || {
+                    if bits == 0 {
+                        return None;
+                    }
+                    let s = bits.trailing_zeros() as usize;
+                    bits &= bits - 1;
+                    Some((tx * SPARSE_TILE + s, ty * SPARSE_TILE + r))
+                })
+            })
+        })
+    }
+    fn resize(&mut self, new_w: usize, new_h: usize) {
+        let outside: Vec<(usize, usize)> = self
+            .iter_set()
+            .filter(|&(x, y)| x >= new_w || y >= new_h)
+            .collect();
+        for (x, y) in outside {
+            self.clr(x, y);
+        }
+        self.w = new_w;
+        self.h = new_h;
+    }
+    fn to_dense(&self) -> CellField {
+        let mut cf = CellField::new(self.w, self.h);
+        for (x, y) in self.iter_set() {
+            cf.set(x, y);
+        }
+        cf
+    }
+}
+
+// a fire next to at least one cell that is not burning, fires at the edge of the
+// grid count as perimeter too
+fn is_perimeter_fire(x: usize, y: usize, fire_field: &CellField, eight: bool) -> bool {
+    let full = match eight {
+        false => 4,
+        true => 8,
+    };
+    fire_field.neighbor_count(x, y, eight) < full
+}
+
+fn find_root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+// union-find over 4-adjacent fires, ids are numbered 0..count in fire order
+fn label_fire_clusters(fires: &[Fire], w: usize) -> (Vec<usize>, usize) {
+    let mut parent: Vec<usize> = (0..fires.len()).collect();
+    let mut at: HashMap<usize, usize> = HashMap::with_capacity(fires.len());
+    for (i, f) in fires.iter().enumerate() {
+        if let Some(j) = at.insert(f.y * w + f.x, i) {
+            let (a, b) = (find_root(&mut parent, i), find_root(&mut parent, j));
+            parent[a] = b;
+        }
+    }
+    for (i, f) in fires.iter().enumerate() {
+        let left = f.x.checked_sub(1).map(|x| f.y * w + x);
+        let up = f.y.checked_sub(1).map(|y| y * w + f.x);
+        for j in [left, up].into_iter().flatten().filter_map(|c| at.get(&c)) {
+            let (a, b) = (find_root(&mut parent, i), find_root(&mut parent, *j));
+            parent[a] = b;
+        }
+    }
+    let mut ids = vec![usize::MAX; fires.len()];
+    let mut count = 0;
+    for i in 0..fires.len() {
+        let root = find_root(&mut parent, i);
+        if ids[root] == usize::MAX {
+            ids[root] = count;
+            count += 1;
+        }
+        ids[i] = ids[root];
+    }
+    (ids, count)
+}
+
+// cluster sizes seen so far, bin b counts sizes in 2^b..2^(b+1)
+struct ClusterHistogram {
+    bins: [usize; 32],
+}
+
+impl ClusterHistogram {
+    fn new() -> ClusterHistogram {
+        ClusterHistogram { bins: [0; 32] }
+    }
+    fn add(&mut self, size: usize) {
+        let b = (usize::BITS - 1 - size.leading_zeros()) as usize;
+        self.bins[b.min(31)] += 1;
+    }
+}
+
+// steps kept for the time series graphs
+const HISTORY_LEN: usize = 300;
+// steps kept for the phase portrait, and the fire fraction at its top
+const PHASE_LEN: usize = 1000;
+const PHASE_MAX_FIRES: f32 = 0.01;
+
+// the last HISTORY_LEN values pushed, zeros before that
+struct RingBuffer<T> {
+    data: [T; HISTORY_LEN],
+    head: usize,
+}
+
+impl<T: Copy + Default> RingBuffer<T> {
+    fn new() -> RingBuffer<T> {
+        RingBuffer {
+            data: [T::default(); HISTORY_LEN],
+            head: 0,
+        }
+    }
+    fn push(&mut self, v: T) {
+        self.data[self.head] = v;
+        self.head = (self.head + 1) % HISTORY_LEN;
+    }
+    fn as_slice_oldest_first(&self) -> Vec<T> {
+        [&self.data[self.head..], &self.data[..self.head]].concat()
+    }
+}
+
+struct SimStats {
+    tree_count: usize,
+    empty_count: usize,
+    density: f64,
+    charred_frac: f32,
+    seedling_frac: f32,
+    mature_frac: f32,
+    fire_count: usize,
+    max_fire_age: usize,
+    burned_last: usize,
+    extinguished_last: usize,
+    burned_total: u64,
+    burn_rate_buf: [f32; 60],
+    buf_idx: usize,
+    // fractions of the cells burning and holding trees
+    fire_history: RingBuffer<f32>,
+    tree_history: RingBuffer<f32>,
+    // (tree density, fire fraction) of the last PHASE_LEN steps, oldest first
+    phase_history: VecDeque<(f32, f32)>,
+    dirty_pixels_uploaded: u64,
+    pending_saves: usize,
+    drops_remaining: usize,
+    // of the density overlay's map, while it is shown
+    box_density: Option<(f32, f32)>,
+    rain_events: u64,
+    drought: bool,
+    drought_steps: u64,
+    // of all steps so far
+    drought_frac: f32,
+    cluster_count: usize,
+    largest_cluster: usize,
+    largest_forest: usize,
+    cluster_hist: ClusterHistogram,
+    mean_age: f32,
+    p90_age: u16,
+    season: f32,
+    max_burn_count: u32,
+    mean_burn_count: f32,
+}
+
+impl SimStats {
+    fn new() -> SimStats {
+        SimStats {
+            tree_count: 0,
+            empty_count: 0,
+            density: 0.,
+            charred_frac: 0.,
+            seedling_frac: 0.,
+            mature_frac: 0.,
+            fire_count: 0,
+            max_fire_age: 0,
+            burned_last: 0,
+            extinguished_last: 0,
+            burned_total: 0,
+            burn_rate_buf: [0.; 60],
+            buf_idx: 0,
+            fire_history: RingBuffer::new(),
+            tree_history: RingBuffer::new(),
+            phase_history: VecDeque::with_capacity(PHASE_LEN),
+            dirty_pixels_uploaded: 0,
+            pending_saves: 0,
+            drops_remaining: 0,
+            box_density: None,
+            rain_events: 0,
+            drought: false,
+            drought_steps: 0,
+            drought_frac: 0.,
+            cluster_count: 0,
+            largest_cluster: 0,
+            largest_forest: 0,
+            cluster_hist: ClusterHistogram::new(),
+            mean_age: 0.,
+            p90_age: 0,
+            season: 1.,
+            max_burn_count: 0,
+            mean_burn_count: 0.,
+        }
+    }
+    fn update(&mut self, cf: &CellField, fires: &[Fire], burned: usize) {
+        self.tree_count = cf.count_set();
+        self.empty_count = cf.count_clr();
+        self.density = cf.density();
+        self.fire_count = fires.len();
+        self.max_fire_age = fires.iter().map(|f| f.age).max().unwrap_or(0);
+        self.burned_last = burned;
+        self.burned_total += burned as u64;
+        self.burn_rate_buf[self.buf_idx] = burned as f32;
+        self.buf_idx = (self.buf_idx + 1) % self.burn_rate_buf.len();
+        let cells = (cf.w * cf.h).max(1) as f32;
+        self.fire_history.push(fires.len() as f32 / cells);
+        self.tree_history.push(self.density as f32);
+        if self.phase_history.len() == PHASE_LEN {
+            self.phase_history.pop_front();
+        }
+        self.phase_history
+            .push_back((self.density as f32, fires.len() as f32 / cells));
+    }
+    fn update_stages(&mut self, charred: usize, seedlings: usize) {
+        let cells = (self.tree_count + self.empty_count) as f32;
+        self.charred_frac = charred as f32 / cells;
+        self.seedling_frac = seedlings as f32 / cells;
+        self.mature_frac = self.tree_count.saturating_sub(seedlings) as f32 / cells;
+    }
+    fn update_clusters(&mut self, fires: &[Fire], w: usize) {
+        let (ids, count) = label_fire_clusters(fires, w);
+        let mut sizes = vec![0; count];
+        for id in ids {
+            sizes[id] += 1;
+        }
+        for &size in &sizes {
+            self.cluster_hist.add(size);
+        }
+        self.cluster_count = count;
+        self.largest_cluster = sizes.into_iter().max().unwrap_or(0);
+    }
+    fn update_forest(&mut self, cf: &CellField) {
+        self.largest_forest = cf.largest_component_size();
+    }
+    fn update_ages(&mut self, cf: &CellField, ages: &[u16]) {
+        let mut tree_ages: Vec<u16> = cf.iter_set().map(|(x, y)| ages[y * cf.w + x]).collect();
+        if tree_ages.is_empty() {
+            self.mean_age = 0.;
+            self.p90_age = 0;
+            return;
+        }
+        let sum: u64 = tree_ages.iter().map(|&a| a as u64).sum();
+        self.mean_age = sum as f32 / tree_ages.len() as f32;
+        let k = (tree_ages.len() - 1) * 9 / 10;
+        self.p90_age = *tree_ages.select_nth_unstable(k).1;
+    }
+    fn update_burns(&mut self, counts: &[u32]) {
+        self.max_burn_count = counts.iter().copied().max().unwrap_or(0);
+        let sum: u64 = counts.iter().map(|&n| n as u64).sum();
+        self.mean_burn_count = sum as f32 / counts.len().max(1) as f32;
+    }
+    fn burn_rate(&self) -> f32 {
+        self.burn_rate_buf.iter().sum::<f32>() / self.burn_rate_buf.len() as f32
+    }
+}
+
+// the last 60 frame times, for a rolling fps
+struct FrameTimes {
+    buf: [f32; 60],
+    idx: usize,
+}
+
+impl FrameTimes {
+    fn new() -> FrameTimes {
+        FrameTimes {
+            buf: [0.; 60],
+            idx: 0,
+        }
+    }
+    fn add(&mut self, dt: f32) {
+        self.buf[self.idx] = dt;
+        self.idx = (self.idx + 1) % self.buf.len();
+    }
+    fn fps(&self) -> f32 {
+        let sum: f32 = self.buf.iter().sum();
+        match sum > 0. {
+            false => 0.,
+            true => self.buf.iter().filter(|&&t| t > 0.).count() as f32 / sum,
+        }
+    }
+}
+
+// 2d site percolation thresholds on the square lattice
+const P_C_4: f64 = 0.5927;
+const P_C_8: f64 = 0.4073;
+// site percolation with six neighbors, as on the triangular lattice
+const P_C_HEX: f64 = 0.5;
+
+fn percolation_threshold(eight: bool, grid: GridType) -> f64 {
+    match (grid, eight) {
+        (GridType::Hexagonal, _) => P_C_HEX,
+        (GridType::Square, false) => P_C_4,
+        (GridType::Square, true) => P_C_8,
+    }
+}
+
+// distance of the tree density from the percolation threshold and how it compares
+fn percolation_status(density: f64, eight: bool, grid: GridType) -> (f64, &'static str) {
+    let offset = density - percolation_threshold(eight, grid);
+    let status = match offset {
+        o if o < -0.02 => "below",
+        o if o <= 0.02 => "critical",
+        _ => "above",
+    };
+    (offset, status)
+}
+
+// buf as a line in the w x h box at (x, y), scaled from 0 at the bottom to its
+// largest value at the top, with lines at 0, half and all of that
+fn draw_time_series(buf: &RingBuffer<f32>, x: f32, y: f32, w: f32, h: f32, color: Color) {
+    let values = buf.as_slice_oldest_first();
+    let max = values.iter().copied().fold(0., f32::max);
+    let faint = Color::new(1., 1., 1., 0.3);
+    for t in [0.0f32, 0.5, 1.0] {
+        draw_line(x, y + h * (1. - t), x + w, y + h * (1. - t), 1., faint);
+    }
+    let px = |i: usize| x + w * i as f32 / (HISTORY_LEN - 1) as f32;
+    let py = |v: f32| y + h * (1. - v / max.max(f32::EPSILON));
+    for (i, pair) in values.windows(2).enumerate() {
+        draw_line(px(i), py(pair[0]), px(i + 1), py(pair[1]), 1., color);
+    }
+    draw_text(&format!("{:.3}", max), x + w + 4., y + 10., 16., color);
+}
+
+// fire fraction over tree density in the w x h box at (x, y), older steps
+// darker, the current one a large dot and the percolation threshold p_c dashed
+fn draw_phase_portrait(hist: &VecDeque<(f32, f32)>, x: f32, y: f32, w: f32, h: f32, p_c: f32) {
+    draw_rectangle(x, y, w, h, Color::new(0., 0., 0., 0.7));
+    draw_rectangle_lines(x, y, w, h, 1., GRAY);
+    draw_text("Phase Portrait", x + 4., y + 14., 16., WHITE);
+    let px = |d: f32| x + w * d.clamp(0., 1.);
+    let py = |f: f32| y + h * (1. - (f / PHASE_MAX_FIRES).clamp(0., 1.));
+    let dash = 6.;
+    let mut dy = 0.;
+    while dy < h {
+        let end = (dy + dash).min(h);
+        draw_line(px(p_c), y + dy, px(p_c), y + end, 1., YELLOW);
+        dy += 2. * dash;
+    }
+    for (i, &(d, f)) in hist.iter().enumerate() {
+        let b = 0.15 + 0.85 * (i + 1) as f32 / hist.len() as f32;
+        draw_circle(px(d), py(f), 1., Color::new(b, b * 0.6, 0., 1.));
+    }
+    if let Some(&(d, f)) = hist.back() {
+        draw_circle(px(d), py(f), 4., WHITE);
+    }
+    draw_text("density 0..1, fires 0..0.01", x + 4., y + h - 4., 14., GRAY);
+}
+
+fn draw_stats(
+    stats: &SimStats,
+    frno: usize,
+    eight: bool,
+    grid: GridType,
+    fps: f32,
+    target_fps: f32,
+) {
+    let size = vec2(260., 400.);
+    // the last HISTORY_LEN steps above the window, fires over trees
+    let top = screen_height() - size.y - 10. - 2. * 70.;
+    draw_time_series(&stats.fire_history, 10., top, 300., 60., ORANGE);
+    draw_time_series(&stats.tree_history, 10., top + 70., 300., 60., GREEN);
+    draw_phase_portrait(
+        &stats.phase_history,
+        size.x + 20.,
+        screen_height() - 210.,
+        200.,
+        200.,
+        percolation_threshold(eight, grid) as f32,
+    );
+    widgets::Window::new(hash!(), vec2(10., screen_height() - size.y - 10.), size)
+        .label("Stats")
+        .movable(false)
+        .ui(&mut root_ui(), |ui| {
+            ui.label(None, &format_window_title(stats, frno));
+            ui.label(
+                None,
+                &format!("trees: {} ({:.1}%)", stats.tree_count, stats.density * 100.),
+            );
+            ui.label(None, &format!("empty: {}", stats.empty_count));
+            if let Some((mean, std)) = stats.box_density {
+                ui.label(None, &format!("local density: {:.3} +- {:.3}", mean, std));
+            }
+            let (offset, status) = percolation_status(stats.density, eight, grid);
+            let color = match status {
+                "below" => GREEN,
+                "critical" => YELLOW,
+                _ => RED,
+            };
+            let style = ui.style_builder().text_color(color).build();
+            let skin = Skin {
+                label_style: style,
+                ..ui.default_skin()
+            };
+            ui.push_skin(&skin);
+            ui.label(
+                None,
+                &format!("density: {:.3} ({:+.3}, {})", stats.density, offset, status),
+            );
+            ui.pop_skin();
+            let cells = (stats.tree_count + stats.empty_count).max(1);
+            ui.label(
+                None,
+                &format!(
+                    "largest forest: {} ({:.1}%)",
+                    stats.largest_forest,
+                    stats.largest_forest as f64 * 100. / cells as f64
+                ),
+            );
+            ui.label(
+                None,
+                &format!(
+                    "charred/seedling/mature: {:.1}/{:.1}/{:.1}%",
+                    stats.charred_frac * 100.,
+                    stats.seedling_frac * 100.,
+                    stats.mature_frac * 100.
+                ),
+            );
+            ui.label(
+                None,
+                &format!(
+                    "tree age: mean {:.0}, p90 {}",
+                    stats.mean_age, stats.p90_age
+                ),
+            );
+            ui.label(None, &format!("fires: {}", stats.fire_count));
+            ui.label(None, &format!("season: x{:.2} growth", stats.season));
+            if stats.drought {
+                let style = ui.style_builder().text_color(ORANGE).build();
+                let skin = Skin {
+                    label_style: style,
+                    ..ui.default_skin()
+                };
+                ui.push_skin(&skin);
+                ui.label(None, "DROUGHT");
+                ui.pop_skin();
+            }
+            ui.label(None, &format!("burned: {}", stats.burned_total));
+            ui.label(
+                None,
+                &format!(
+                    "burns per cell: max {}, mean {:.2}",
+                    stats.max_burn_count, stats.mean_burn_count
+                ),
+            );
+            ui.label(None, &format!("burn rate: {:.1}/frame", stats.burn_rate()));
+            ui.label(
+                None,
+                &format!("uploaded: {} px", stats.dirty_pixels_uploaded),
+            );
+            ui.label(None, &format!("pngs waiting: {}", stats.pending_saves));
+            ui.label(
+                None,
+                &format!("tanker drops left: {}", stats.drops_remaining),
+            );
+            ui.label(None, &format!("rain events: {}", stats.rain_events));
+            ui.label(
+                None,
+                &format!("in drought: {:.1}% of the time", stats.drought_frac * 100.),
+            );
+            ui.label(None, &format!("fps: {:.0} of {:.0}", fps, target_fps));
+            ui.label(
+                None,
+                &format!(
+                    "fire clusters: {}, largest {}",
+                    stats.cluster_count, stats.largest_cluster
+                ),
+            );
+            let last = stats.cluster_hist.bins.iter().rposition(|&n| n > 0);
+            let bins = &stats.cluster_hist.bins[..last.map_or(0, |b| b + 1)];
+            ui.label(None, &format!("log2 sizes: {:?}", bins));
+        });
+}
+
+enum CellState {
+    Empty,
+    Tree,
+    Burning(usize),
+}
+
+impl std::fmt::Debug for CellState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CellState::Empty => write!(f, "empty"),
+            CellState::Tree => write!(f, "tree"),
+            CellState::Burning(age) => write!(f, "burning(age={})", age),
+        }
+    }
+}
+
+fn get_cell_state(x: usize, y: usize, cf: &CellField, fires: &[Fire]) -> CellState {
+    if let Some(fire) = fires.iter().find(|f| f.x == x && f.y == y) {
+        return CellState::Burning(fire.age);
+    }
+    match cf.get(x, y) {
+        true => CellState::Tree,
+        false => CellState::Empty,
+    }
+}
+
+// drawn below right of the cursor, kept inside the window. density is the box
+// filtered density map, shown when zoomed in
+fn draw_tooltip(
+    x: usize,
+    y: usize,
+    cf: &CellField,
+    fires: &[Fire],
+    density: Option<&[f32]>,
+    camera: &Camera,
+) {
+    let mut text = format!(
+        "(x={}, y={}, state={:?})",
+        x,
+        y,
+        get_cell_state(x, y, cf, fires)
+    );
+    if let (true, Some(map)) = (camera.zoom > 1., density) {
+        text += &format!(" density={:.2}", map[y * cf.w + x]);
+    }
+    let dims = measure_text(&text, None, 16, 1.0);
+    let (mx, my) = mouse_position();
+    let tx = (mx + 16.).min(screen_width() - dims.width).max(0.);
+    let ty = (my + 24.).min(screen_height()).max(dims.height);
+    draw_rectangle(
+        tx - 2.,
+        ty - dims.height - 2.,
+        dims.width + 4.,
+        dims.height + 6.,
+        BLACK,
+    );
+    draw_text(&text, tx, ty, 16., WHITE);
+}
+
+struct CliArgs {
+    width: Option<i32>,
+    height: Option<i32>,
+    fire_prob: Option<f32>,
+    tree_prob: Option<f32>,
+    color_speed: Option<f32>,
+    fire_max_age: Option<f32>,
+    eight_conn: bool,
+    seed: Option<u64>,
+    steps: Option<f32>,
+    headless: Option<usize>,
+    no_ui: bool,
+    no_shader: bool,
+    compare: bool,
+    import_png: Option<String>,
+    stats_csv: Option<String>,
+    mmap_state: Option<String>,
+    export_numpy: Option<String>,
+    stats_interval: usize,
+    ignite_clusters: Option<String>,
+    ignition_map: Option<String>,
+    roads: Option<String>,
+    road_width: usize,
+    water_png: Option<String>,
+}
+
+impl CliArgs {
+    fn new() -> CliArgs {
+        CliArgs {
+            width: None,
+            height: None,
+            fire_prob: None,
+            tree_prob: None,
+            color_speed: None,
+            fire_max_age: None,
+            eight_conn: false,
+            seed: None,
+            steps: None,
+            headless: None,
+            no_ui: false,
+            no_shader: false,
+            compare: false,
+            import_png: None,
+            stats_csv: None,
+            mmap_state: None,
+            export_numpy: None,
+            stats_interval: 1,
+            ignite_clusters: None,
+            ignition_map: None,
+            roads: None,
+            road_width: 3,
+            water_png: None,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+const USAGE: &str = "usage: macroquad-forestfire [options]
+  --width N       window width
+  --height N      window height
+  --fire-prob F   spontaneous fire probability per cell and step
+  --tree-prob F   tree growth probability per cell and step
+  --color-speed F how fast the color of new trees cycles
+  --fire-max-age A  steps a cell burns before it can go out, at least 1
+  --eight-conn    spread to diagonal neighbors too
+  --seed S        random seed
+  --steps N       simulation steps per 1/60 s
+  --headless N    run N steps without a window, print how long they took and exit
+  --no-ui         hide the controls, stats and tooltip
+  --no-shader     render on the CPU
+  --compare       run two simulations side by side from the same seed
+  --import-png P  start from the trees (green) and fires (red) in a 24 or 32 bit png
+  --ignition-map P  scale lightning by the brightness of the png P, white is 1
+  --ignite-clusters P  set the trees in each \"x y radius\" disk listed in P on fire
+  --roads P       lay a road along each \"x1 y1 x2 y2\" segment listed in P
+  --road-width N  cells either side of a road's center line, 3 by default
+  --water-png P   make the blue pixels of the png P water
+  --stats-csv P   write the stats to the csv file P
+  --mmap-state P  keep the trees in the file P, the next run of the same size
+                  starts from them
+  --export-numpy P  write every step to the .npy file P as a frames x height x
+                  width uint8 array, 0 empty, 1 tree, 2 burning, 3 charred
+  --stats-interval N  every N steps, 1 by default
+  --help          show this message";
+
+#[cfg(not(target_arch = "wasm32"))]
+fn cli_value<T: std::str::FromStr>(flag: &str, args: &mut impl Iterator<Item = String>) -> T {
+    match args.next().and_then(|s| s.parse().ok()) {
+        Some(v) => v,
+        None => {
+            eprintln!("{} needs a valid value\n{}", flag, USAGE);
+            exit(2);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_cli() -> CliArgs {
+    let mut cli = CliArgs::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" => cli.width = Some(cli_value(&arg, &mut args)),
+            "--height" => cli.height = Some(cli_value(&arg, &mut args)),
+            "--fire-prob" => cli.fire_prob = Some(cli_value(&arg, &mut args)),
+            "--tree-prob" => cli.tree_prob = Some(cli_value(&arg, &mut args)),
+            "--color-speed" => cli.color_speed = Some(cli_value(&arg, &mut args)),
+            "--fire-max-age" => cli.fire_max_age = Some(cli_value(&arg, &mut args)),
+            "--eight-conn" => cli.eight_conn = true,
+            "--seed" => cli.seed = Some(cli_value(&arg, &mut args)),
+            "--steps" => cli.steps = Some(cli_value(&arg, &mut args)),
+            "--headless" => cli.headless = Some(cli_value(&arg, &mut args)),
+            "--no-ui" => cli.no_ui = true,
+            "--no-shader" => cli.no_shader = true,
+            "--compare" => cli.compare = true,
+            "--import-png" => cli.import_png = Some(cli_value(&arg, &mut args)),
+            "--ignition-map" => cli.ignition_map = Some(cli_value(&arg, &mut args)),
+            "--roads" => cli.roads = Some(cli_value(&arg, &mut args)),
+            "--road-width" => cli.road_width = cli_value(&arg, &mut args),
+            "--water-png" => cli.water_png = Some(cli_value(&arg, &mut args)),
+            "--ignite-clusters" => cli.ignite_clusters = Some(cli_value(&arg, &mut args)),
+            "--stats-csv" => cli.stats_csv = Some(cli_value(&arg, &mut args)),
+            "--mmap-state" => cli.mmap_state = Some(cli_value(&arg, &mut args)),
+            "--export-numpy" => cli.export_numpy = Some(cli_value(&arg, &mut args)),
+            "--stats-interval" => cli.stats_interval = cli_value::<usize>(&arg, &mut args).max(1),
+            "--help" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            _ => {
+                eprintln!("unknown option {}\n{}", arg, USAGE);
+                exit(2);
+            }
+        }
+    }
+    cli
+}
+
+#[cfg(target_arch = "wasm32")]
+fn parse_cli() -> CliArgs {
+    CliArgs::new()
+}
+
+#[cfg(feature = "multistate")]
+const EMPTY: u8 = 0;
+#[cfg(feature = "multistate")]
+const SEEDLING: u8 = 1;
+#[cfg(feature = "multistate")]
+const MATURE: u8 = 2;
+#[cfg(feature = "multistate")]
+const CHARRED: u8 = 3;
+
+const SEEDLING_IGNITION: f32 = 0.3;
+const SEEDLING_COLOR: Color = Color::new(0.4, 0.8, 0.3, 1.0);
+const CHARRED_COLOR: Color = Color::new(0.25, 0.2, 0.2, 1.0);
+// burned out cells that can not catch again yet
+const COOLDOWN_COLOR: Color = Color::new(0.35, 0.15, 0.15, 1.0);
+
+struct SpeciesParams {
+    name: &'static str,
+    growth_weight: f32,
+    ignition_prob: f32,
+    base_color: Color,
+}
+
+// pine burns readily, oak and aspen resist
+const SPECIES: [SpeciesParams; 3] = [
+    SpeciesParams {
+        name: "pine",
+        growth_weight: 0.5,
+        ignition_prob: 1.0,
+        base_color: Color::new(0.0, 0.4, 0.15, 1.0),
+    },
+    SpeciesParams {
+        name: "oak",
+        growth_weight: 0.3,
+        ignition_prob: 0.5,
+        base_color: Color::new(0.25, 0.45, 0.05, 1.0),
+    },
+    SpeciesParams {
+        name: "aspen",
+        growth_weight: 0.2,
+        ignition_prob: 0.25,
+        base_color: Color::new(0.6, 0.75, 0.2, 1.0),
+    },
+];
+const NO_SPECIES: u8 = 3;
+
+fn pick_species() -> u8 {
+    let total: f32 = SPECIES.iter().map(|s| s.growth_weight).sum();
+    let mut r = rand::gen_range(0.0f32, total);
+    for (i, s) in SPECIES.iter().enumerate() {
+        if r < s.growth_weight {
+            return i as u8;
+        }
+        r -= s.growth_weight;
+    }
+    SPECIES.len() as u8 - 1
+}
+
+// base color shifted a quarter of the way to the colorphase color
+fn species_color(species: u8, colorphase: f32) -> Color {
+    let c = SPECIES[species as usize].base_color;
+    Color::new(
+        0.75 * c.r,
+        0.75 * c.g + 0.25 * colorphase.cos().abs(),
+        0.75 * c.b + 0.25 * colorphase.sin().abs(),
+        1.0,
+    )
+}
+
+const HELP_LINES: [&str; 18] = [
+    "Space  controls",
+    "P      pause",
+    "N      step while paused",
+    "Q      quit",
+    "S      stats",
+    "Ctrl+S screenshot",
+    "Ctrl+Z/Y undo and redo tree edits",
+    "H      help, Esc hides it",
+    "F/T/E  fire, tree and erase brush",
+    "G/V    flood fill and rectangle tool",
+    "Ctrl+drag with V copies, Ctrl+V pastes",
+    "W      draw water",
+    "U+click  tanker drop",
+    "K      kill fires",
+    "C      clear trees",
+    "R      reset the heatmap",
+    "Left/Right/Home  step through a replay",
+    "scroll or pinch to zoom, middle drag pans",
+];
+
+fn draw_help_overlay(w: f32, h: f32) {
+    let (x, y) = (w * 0.2, h * 0.2);
+    draw_rectangle(x, y, w * 0.6, h * 0.6, Color::new(0., 0., 0., 0.75));
+    let size = 16. * h / 600.;
+    for (i, line) in HELP_LINES.iter().enumerate() {
+        draw_text(
+            line,
+            x + size,
+            y + size * (1.5 + 1.2 * i as f32),
+            size,
+            WHITE,
+        );
+    }
+}
+
+const TUTORIAL_STEPS: usize = 10;
+// simulation steps each hint stays up unless Enter skips it
+const TUTORIAL_STEP_LEN: usize = 500;
+
+fn tutorial_hint(step: usize) -> &'static str {
+    match step {
+        0 => "Watch the green pixels grow - those are trees.",
+        1 => "A red flash is a spontaneous fire.",
+        2 => "Fires spread to neighboring trees and burn out after a few steps.",
+        3 => "Burned ground stays charred for a while before seedlings return.",
+        4 => "Press F, T or E and drag to paint fires, trees or firebreaks.",
+        5 => "Scroll or pinch to zoom, drag with the middle button to pan.",
+        6 => "Press S for statistics, density near 0.59 is the critical point.",
+        7 => "Ctrl+Z and Ctrl+Y undo and redo your tree edits.",
+        8 => "Press H for the list of keys.",
+        _ => "Press Space to open the parameter controls.",
+    }
+}
+
+// hints for the first runs, shown until the last one was seen once
+struct TutorialState {
+    step: usize,
+    visible: bool,
+}
+
+impl TutorialState {
+    fn new() -> TutorialState {
+        TutorialState {
+            step: 0,
+            visible: !state::tutorial_done(),
+        }
+    }
+    // Enter or enough simulated steps move on to the next hint
+    fn update(&mut self, frno: usize) {
+        if !self.visible {
+            return;
+        }
+        if is_key_pressed(KeyCode::Enter) || frno >= TUTORIAL_STEP_LEN * (self.step + 1) {
+            self.step += 1;
+        }
+        if self.step >= TUTORIAL_STEPS {
+            self.visible = false;
+            state::mark_tutorial_done();
+        }
+    }
+    fn draw(&self) {
+        if !self.visible {
+            return;
+        }
+        let (w, h) = (screen_width(), screen_height());
+        draw_rectangle(0., h - 48., w, 48., Color::new(0., 0., 0., 0.6));
+        let text = tutorial_hint(self.step);
+        let dims = measure_text(text, None, 22, 1.0);
+        draw_text(text, (w - dims.width) / 2., h - 24., 22., WHITE);
+        let count = format!(
+            "{}/{}, Enter for the next hint",
+            self.step + 1,
+            TUTORIAL_STEPS
+        );
+        let dims = measure_text(&count, None, 16, 1.0);
+        draw_text(&count, (w - dims.width) / 2., h - 8., 16., GRAY);
+    }
+}
+
+// a short message at the top of the window for ttl frames
+struct Toast {
+    text: String,
+    ttl: usize,
+}
+
+fn draw_toast(t: &Toast) {
+    let dims = measure_text(&t.text, None, 24, 1.0);
+    let x = (screen_width() - dims.width) / 2.;
+    draw_rectangle(
+        x - 10.,
+        10.,
+        dims.width + 20.,
+        dims.height + 16.,
+        Color::new(0., 0., 0., 0.6),
+    );
+    draw_text(&t.text, x, 18. + dims.offset_y, 24., WHITE);
+}
+
+fn draw_species_legend() {
+    let x = screen_width() - 90.;
+    for (i, s) in SPECIES.iter().enumerate() {
+        let y = 20. + 20. * i as f32;
+        draw_rectangle(x, y, 12., 12., s.base_color);
+        draw_text(s.name, x + 18., y + 11., 20., WHITE);
+    }
+}
+
+// matplotlib's viridis sampled at 13 points
+const VIRIDIS: [(f32, f32, f32); 13] = [
+    (0.267, 0.004, 0.329),
+    (0.280, 0.118, 0.437),
+    (0.264, 0.225, 0.506),
+    (0.231, 0.320, 0.541),
+    (0.193, 0.405, 0.554),
+    (0.159, 0.487, 0.556),
+    (0.129, 0.569, 0.549),
+    (0.133, 0.644, 0.523),
+    (0.222, 0.719, 0.465),
+    (0.373, 0.784, 0.378),
+    (0.566, 0.838, 0.261),
+    (0.783, 0.880, 0.148),
+    (0.992, 0.906, 0.145),
+];
+
+fn viridis(t: f32) -> Color {
+    let x = t.clamp(0., 1.) * 12.;
+    let i = (x as usize).min(11);
+    let f = x - i as f32;
+    let (a, b) = (VIRIDIS[i], VIRIDIS[i + 1]);
+    Color::new(
+        a.0 + (b.0 - a.0) * f,
+        a.1 + (b.1 - a.1) * f,
+        a.2 + (b.2 - a.2) * f,
+        1.0,
+    )
+}
+
+// burn counts in viridis, living trees lightened towards white
+fn paint_heatmap(counts: &[u32], cf: &CellField, img: &mut Image) {
+    let max = counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+    for (i, &n) in counts.iter().enumerate() {
+        let (x, y) = (i % cf.w, i / cf.w);
+        let mut c = viridis(n as f32 / max);
+        if cf.get(x, y) {
+            c = Color::new(
+                c.r + (1. - c.r) * 0.3,
+                c.g + (1. - c.g) * 0.3,
+                c.b + (1. - c.b) * 0.3,
+                1.0,
+            );
+        }
+        img.set_pixel(x as u32, y as u32, c);
+    }
+}
+
+// translucent blue, stronger where the forest is denser, drawn over the cells
+fn paint_density(map: &[f32], w: usize, img: &mut Image) {
+    for (i, &d) in map.iter().enumerate() {
+        let c = Color::new(0., 0., d * 0.5, d * 0.3);
+        img.set_pixel((i % w) as u32, (i / w) as u32, c);
+    }
+}
+
+// mean and standard deviation
+fn mean_std(values: &[f32]) -> (f32, f32) {
+    let n = values.len().max(1) as f64;
+    let mean = values.iter().map(|&v| v as f64).sum::<f64>() / n;
+    let var = values
+        .iter()
+        .map(|&v| (v as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n;
+    (mean as f32, var.sqrt() as f32)
+}
+
+// steps between fires in a cell that burned n times over n_history steps, cells
+// that never burned get the whole history
+fn fire_return_interval(n: u32, n_history: usize) -> f32 {
+    n_history as f32 / n.max(1) as f32
+}
+
+// blue through white to red
+fn diverging(t: f32) -> Color {
+    let t = t.clamp(0., 1.);
+    match t < 0.5 {
+        true => Color::new(0.2 + 1.6 * t, 0.3 + 1.4 * t, 1.0, 1.0),
+        false => Color::new(1.0, 1.7 - 1.4 * t, 1.8 - 1.6 * t, 1.0),
+    }
+}
+
+// fire return interval on a log scale, frequent fires red and rare ones blue
+fn paint_fri(counts: &[u32], n_history: usize, w: usize, img: &mut Image) {
+    let lmax = (n_history.max(2) as f32).ln();
+    for (i, &n) in counts.iter().enumerate() {
+        let t = fire_return_interval(n, n_history).max(1.).ln() / lmax;
+        img.set_pixel((i % w) as u32, (i / w) as u32, diverging(1. - t));
+    }
+}
+
+// trees are drawn darker with age up to this many steps
+const MAX_DISPLAY_AGE: u16 = 5000;
+
+fn tree_age_color(age: u16) -> Color {
+    let t = age.min(MAX_DISPLAY_AGE) as f32 / MAX_DISPLAY_AGE as f32;
+    Color::new(0.5 * (1. - t), 0.9 - 0.6 * t, 0.3 * (1. - t), 1.0)
+}
+
+// step + 1 at which each cell burned out, 0 for cells that are not regrowing
+struct CharField {
+    arr: Vec<u32>,
+    w: usize,
+    h: usize,
+}
+
+impl CharField {
+    fn new(w: usize, h: usize) -> CharField {
+        CharField {
+            arr: vec![0; w * h],
+            w,
+            h,
+        }
+    }
+    fn get(&self, x: usize, y: usize) -> u32 {
+        self.arr[y * self.w + x]
+    }
+    fn mark(&mut self, x: usize, y: usize, frno: usize) {
+        self.arr[y * self.w + x] = frno as u32 + 1;
+    }
+    fn resize(&mut self, new_w: usize, new_h: usize) {
+        let mut resized = CharField::new(new_w, new_h);
+        for y in 0..self.h.min(new_h) {
+            for x in 0..self.w.min(new_w) {
+                resized.arr[y * new_w + x] = self.get(x, y);
+            }
+        }
+        *self = resized;
+    }
+}
+
+// row-major field of BITS-wide values, 64 / BITS of them per word
+#[cfg(feature = "multistate")]
+struct PackedField<const BITS: usize> {
+    arr: Vec<u64>,
+    w: usize,
+    h: usize,
+}
+
+#[cfg(feature = "multistate")]
+impl<const BITS: usize> PackedField<BITS> {
+    const PER_WORD: usize = 64 / BITS;
+    const MASK: u64 = (1 << BITS) - 1;
+
+    fn new(w: usize, h: usize) -> PackedField<BITS> {
+        PackedField {
+            arr: vec![0; (w * h).div_ceil(Self::PER_WORD)],
+            w,
+            h,
+        }
+    }
+    fn indices(&self, x: usize, y: usize) -> (usize, usize) {
+        let i = y * self.w + x;
+        (i / Self::PER_WORD, (i % Self::PER_WORD) * BITS)
+    }
+    fn get(&self, x: usize, y: usize) -> u8 {
+        let (off, s) = self.indices(x, y);
+        ((self.arr[off] >> s) & Self::MASK) as u8
+    }
+    fn set(&mut self, x: usize, y: usize, v: u8) {
+        let (off, s) = self.indices(x, y);
+        self.arr[off] = (self.arr[off] & !(Self::MASK << s)) | ((v as u64 & Self::MASK) << s);
+    }
+    fn resize(&mut self, new_w: usize, new_h: usize) {
+        let mut resized = PackedField::new(new_w, new_h);
+        for y in 0..self.h.min(new_h) {
+            for x in 0..self.w.min(new_w) {
+                resized.set(x, y, self.get(x, y));
+            }
+        }
+        *self = resized;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum FireColorMode {
+    Classic,
+    Hsv,
+}
+
+// fires spread one ring of neighbors per step, or burn their whole forest at once
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum SpreadMode {
+    StepByStep,
+    FlashFire,
+}
+
+// square cells have 4 or 8 neighbors, hexagonal ones 6, odd rows shifted right
+// by half a cell
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum GridType {
+    Square,
+    Hexagonal,
+}
+
+const GRID_TYPES: [&str; 2] = ["Square", "Hexagonal"];
+
+impl GridType {
+    fn index(self) -> usize {
+        match self {
+            GridType::Square => 0,
+            GridType::Hexagonal => 1,
+        }
+    }
+    fn from_index(i: usize) -> GridType {
+        match i {
+            1 => GridType::Hexagonal,
+            _ => GridType::Square,
+        }
+    }
+}
+
+// how a tree next to fires decides to catch, after wetness, seedlings and species
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum SpreadRule {
+    // any burning neighbor ignites it, whatever the wind and fire intensity
+    Deterministic,
+    // each burning neighbor tries with this chance on top of wind and intensity
+    Independent(f32),
+    // only with at least this many burning neighbors
+    Majority(u8),
+    // only when at least this fraction of its neighbors burn
+    Threshold(f32),
+}
+
+const SPREAD_RULES: [&str; 4] = ["Deterministic", "Independent", "Majority", "Threshold"];
+
+impl SpreadRule {
+    fn index(self) -> usize {
+        match self {
+            SpreadRule::Deterministic => 0,
+            SpreadRule::Independent(_) => 1,
+            SpreadRule::Majority(_) => 2,
+            SpreadRule::Threshold(_) => 3,
+        }
+    }
+    fn from_index(i: usize) -> SpreadRule {
+        match i {
+            0 => SpreadRule::Deterministic,
+            2 => SpreadRule::Majority(2),
+            3 => SpreadRule::Threshold(0.5),
+            _ => SpreadRule::Independent(1.0),
+        }
+    }
+}
+
+fn hsv_to_rgb(hue: f32, sat: f32, val: f32) -> Color {
+    let c = val * sat;
+    let hp = (hue / 60.).rem_euclid(6.);
+    let x = c * (1. - (hp % 2. - 1.).abs());
+    let (r, g, b) = match hp as usize {
+        0 => (c, x, 0.),
+        1 => (x, c, 0.),
+        2 => (0., c, x),
+        3 => (0., x, c),
+        4 => (x, 0., c),
+        _ => (c, 0., x),
+    };
+    let m = val - c;
+    Color::new(r + m, g + m, b + m, 1.0)
+}
+
+// deep red when young to yellow-white when old
+fn fire_hsv_color(age: usize, max_age: f32) -> Color {
+    let t = (age as f32 / max_age).clamp(0., 1.);
+    let hue = 60. * t;
+    let val = 1. + 0.5 * t;
+    let sat = 1. / val;
+    let c = hsv_to_rgb(hue, sat, val);
+    Color::new(c.r.min(1.), c.g.min(1.), c.b.min(1.), 1.0)
+}
+
+// weaker fires are drawn darker
+fn fire_color(fire: &Fire, config: &SimConfig) -> Color {
+    let i = fire.intensity;
+    match config.fire_color_mode {
+        FireColorMode::Classic => Color::new(i, fire.age as f32 / config.firemaxage * i, 0., 1.0),
+        FireColorMode::Hsv => {
+            let c = fire_hsv_color(fire.age, config.firemaxage);
+            Color::new(c.r * i, c.g * i, c.b * i, 1.0)
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default = "SimConfig::new")]
+pub struct SimConfig {
+    logfireprob: f32,
+    logtreeprob: f32,
+    colorspeed: f32,
+    firemaxage: f32,
+    tail_length: f32,
+    eightconn: bool,
+    grid_type: GridType,
+    conn_by_age: bool,
+    age_threshold: f32,
+    torus: bool,
+    #[serde(skip)]
+    wind: Vec2,
+    wind_speed: f32,
+    wind_angle: f32,
+    gravity: f32,
+    logwetness: f32,
+    wet_resistance: f32,
+    dry_rate: f32,
+    logemberprob: f32,
+    ember_distance: f32,
+    intensity_decay: f32,
+    extinction_prob: f32,
+    lograinprob: f32,
+    rain_intensity: f32,
+    rain_wet_duration: f32,
+    logdroughtprob: f32,
+    drought_duration: f32,
+    drought_multiplier: f32,
+    seed: Option<u64>,
+    fire_color_mode: FireColorMode,
+    spread_mode: SpreadMode,
+    spread_rule: SpreadRule,
+    char_duration: f32,
+    seedling_duration: f32,
+    cooldown_frames: f32,
+    window_width: i32,
+    window_height: i32,
+    initial_density: f32,
+    use_perlin_init: bool,
+    noise_scale: f32,
+    noise_threshold: f32,
+    age_color: bool,
+    perimeter_mode: bool,
+    season_period: f32,
+    season_amplitude: f32,
+    season_fire: bool,
+    snapshot_interval: f32,
+    snapshot_dir: String,
+}
+
+impl SimConfig {
+    pub fn new() -> SimConfig {
+        let fireprob: f32 = 1e-6;
+        let treeprob: f32 = 1e-3;
+
+        SimConfig {
+            logfireprob: fireprob.log10(),
+            logtreeprob: treeprob.log10(),
+            colorspeed: 5.,
+            firemaxage: 10.,
+            tail_length: 5.,
+            eightconn: false,
+            grid_type: GridType::Square,
+            conn_by_age: false,
+            age_threshold: 5.,
+            torus: false,
+            wind: vec2(1., 0.),
+            wind_speed: 0.,
+            wind_angle: 0.,
+            gravity: 0.,
+            logwetness: -1.,
+            wet_resistance: 0.9,
+            dry_rate: 1e-3,
+            logemberprob: -4.,
+            ember_distance: 30.,
+            intensity_decay: 0.,
+            extinction_prob: 0.,
+            lograinprob: -4.,
+            rain_intensity: 0.8,
+            rain_wet_duration: 500.,
+            logdroughtprob: -4.,
+            drought_duration: 1000.,
+            drought_multiplier: 1.,
+            seed: None,
+            fire_color_mode: FireColorMode::Classic,
+            spread_mode: SpreadMode::StepByStep,
+            spread_rule: SpreadRule::Independent(1.0),
+            char_duration: 200.,
+            seedling_duration: 300.,
+            cooldown_frames: 100.,
+            window_width: 800,
+            window_height: 600,
+            initial_density: 0.25,
+            use_perlin_init: false,
+            noise_scale: 0.01,
+            noise_threshold: 0.5,
+            age_color: false,
+            perimeter_mode: false,
+            season_period: 10000.,
+            season_amplitude: 0.,
+            season_fire: false,
+            snapshot_interval: 0.,
+            snapshot_dir: ".".to_string(),
+        }
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn from_toml_file(path: &str) -> Result<SimConfig, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        toml::from_str(&text).map_err(|e| e.to_string())
+    }
+    // everything but the window size and the seed, which are not sliders
+    fn apply_preset(&mut self, preset: &SimConfig) {
+        *self = SimConfig {
+            window_width: self.window_width,
+            window_height: self.window_height,
+            seed: self.seed,
+            ..preset.clone()
+        };
+    }
+    // chance a fire of this age goes out, none before firemaxage and rising to
+    // certain over tail_length more steps, right away without a tail
+    fn death_prob(&self, age: usize) -> f32 {
+        let soft = self.firemaxage.floor() as usize;
+        match (age >= soft, self.tail_length > 0.) {
+            (false, _) => 0.,
+            (true, false) => 1.,
+            (true, true) => ((age - soft) as f32 / self.tail_length)
+                .clamp(0., 1.)
+                .powi(2),
+        }
+    }
+    // seasonal swing of the growth rate at step frno, in -season_amplitude..season_amplitude
+    fn season(&self, frno: usize) -> f32 {
+        let phase = std::f64::consts::TAU * frno as f64 / self.season_period as f64;
+        self.season_amplitude * phase.sin() as f32
+    }
+    // ignition probability towards each neighbor, 1 for all of them without wind
+    // or gravity. Both push the fire the same way, gravity down the screen
+    fn spread_probs<const N: usize>(&self, dirs: [Vec2; N]) -> [f32; N] {
+        let mut probs = [1.0f32; N];
+        let push = self.wind.normalize_or_zero() * self.wind_speed + vec2(0., self.gravity);
+        let strength = push.length();
+        if strength > 0. {
+            let pdir = push / strength;
+            let pmax = sigmoid(strength);
+            for (p, d) in probs.iter_mut().zip(dirs) {
+                *p = sigmoid(pdir.dot(d.normalize()) * strength) / pmax;
+            }
+        }
+        probs
+    }
+}
+
+impl Default for SimConfig {
+    fn default() -> SimConfig {
+        SimConfig::new()
+    }
+}
+
+// a value SimConfigBuilder::build turned down
+#[derive(Debug)]
+enum ConfigError {
+    FireProb(f32),
+    TreeProb(f32),
+    FireMaxAge(f32),
+    WindowSize(i32, i32),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigError::FireProb(p) => write!(f, "fire probability {} is not above 0", p),
+            ConfigError::TreeProb(p) => write!(f, "tree probability {} is not above 0", p),
+            ConfigError::FireMaxAge(a) => write!(f, "firemaxage {} is below 1", a),
+            ConfigError::WindowSize(w, h) => {
+                write!(f, "window size {}x{} is below 50x50", w, h)
+            }
+        }
+    }
+}
+
+// changes to a config, checked all at once by build
+struct SimConfigBuilder {
+    config: SimConfig,
+}
+
+impl SimConfig {
+    fn builder() -> SimConfigBuilder {
+        SimConfigBuilder::from_config(SimConfig::default())
+    }
+}
+
+impl SimConfigBuilder {
+    fn from_config(config: SimConfig) -> SimConfigBuilder {
+        SimConfigBuilder { config }
+    }
+    fn fire_prob(mut self, p: f32) -> Self {
+        self.config.logfireprob = p.log10();
+        self
+    }
+    fn tree_prob(mut self, p: f32) -> Self {
+        self.config.logtreeprob = p.log10();
+        self
+    }
+    fn color_speed(mut self, s: f32) -> Self {
+        self.config.colorspeed = s;
+        self
+    }
+    fn fire_max_age(mut self, a: f32) -> Self {
+        self.config.firemaxage = a;
+        self
+    }
+    fn eight_conn(mut self, b: bool) -> Self {
+        self.config.eightconn = b;
+        self
+    }
+    fn seed(mut self, s: u64) -> Self {
+        self.config.seed = Some(s);
+        self
+    }
+    fn window_size(mut self, w: u32, h: u32) -> Self {
+        self.config.window_width = w.min(i32::MAX as u32) as i32;
+        self.config.window_height = h.min(i32::MAX as u32) as i32;
+        self
+    }
+    // whatever the command line sets, over the config so far
+    fn with_cli(mut self, cli: &CliArgs) -> Self {
+        if let Some(s) = cli.seed {
+            self = self.seed(s);
+        }
+        if let Some(p) = cli.fire_prob {
+            self = self.fire_prob(p);
+        }
+        if let Some(p) = cli.tree_prob {
+            self = self.tree_prob(p);
+        }
+        if let Some(s) = cli.color_speed {
+            self = self.color_speed(s);
+        }
+        if let Some(a) = cli.fire_max_age {
+            self = self.fire_max_age(a);
+        }
+        if cli.eight_conn {
+            self = self.eight_conn(true);
+        }
+        let w = cli.width.unwrap_or(self.config.window_width).max(0) as u32;
+        let h = cli.height.unwrap_or(self.config.window_height).max(0) as u32;
+        self.window_size(w, h)
+    }
+    // the probabilities are stored as logs, which are not finite for 0 or less
+    fn build(self) -> Result<SimConfig, ConfigError> {
+        let c = self.config;
+        if !c.logfireprob.is_finite() {
+            return Err(ConfigError::FireProb(10f32.powf(c.logfireprob)));
+        }
+        if !c.logtreeprob.is_finite() {
+            return Err(ConfigError::TreeProb(10f32.powf(c.logtreeprob)));
+        }
+        if c.firemaxage.is_nan() || c.firemaxage < 1. {
+            return Err(ConfigError::FireMaxAge(c.firemaxage));
+        }
+        if c.window_width < 50 || c.window_height < 50 {
+            return Err(ConfigError::WindowSize(c.window_width, c.window_height));
+        }
+        Ok(c)
+    }
+}
+
+impl From<&CliArgs> for SimConfigBuilder {
+    fn from(cli: &CliArgs) -> SimConfigBuilder {
+        SimConfig::builder().with_cli(cli)
+    }
+}
+
+impl From<CliArgs> for SimConfigBuilder {
+    fn from(cli: CliArgs) -> SimConfigBuilder {
+        SimConfigBuilder::from(&cli)
+    }
+}
+
+// built-in parameter sets, checked by the builder, then the [[presets]] tables of
+// forestfire.toml; made once, on first use
+static PRESETS: LazyLock<Vec<(String, SimConfig)>> = LazyLock::new(|| {
+    let classic = SimConfig::default();
+    let builtin = [
+        ("Classic", classic.clone()),
+        (
+            "Firestorm",
+            SimConfig {
+                logfireprob: -5.,
+                logtreeprob: -2.5,
+                eightconn: true,
+                wind_speed: 3.,
+                logemberprob: -2.,
+                ember_distance: 60.,
+                ..classic.clone()
+            },
+        ),
+        (
+            "Critical",
+            SimConfig {
+                logfireprob: -8.,
+                logtreeprob: -3.,
+                logemberprob: -6.,
+                lograinprob: -6.,
+                ..classic.clone()
+            },
+        ),
+        (
+            "Slow Burn",
+            SimConfig {
+                firemaxage: 20.,
+                intensity_decay: 0.02,
+                logtreeprob: -3.5,
+                wet_resistance: 0.95,
+                ..classic.clone()
+            },
+        ),
+        (
+            "Dense Forest",
+            SimConfig {
+                logfireprob: -9.,
+                logtreeprob: -2.2,
+                logemberprob: -5.,
+                ..classic
+            },
+        ),
+    ];
+    let mut presets: Vec<(String, SimConfig)> = builtin
+        .into_iter()
+        .map(|(name, config)| {
+            let config = SimConfigBuilder::from_config(config)
+                .build()
+                .expect("the built-in presets are valid");
+            (name.to_string(), config)
+        })
+        .collect();
+    presets.extend(state::read_presets());
+    presets
+});
+
+fn draw_wind_arrow(config: &SimConfig) {
+    if config.wind_speed <= 0. {
+        return;
+    }
+    let c = vec2(40., 40.);
+    let tip = c + config.wind.normalize_or_zero() * (10. + 2. * config.wind_speed);
+    let back = (c - tip).normalize_or_zero() * 8.;
+    draw_line(c.x, c.y, tip.x, tip.y, 2., WHITE);
+    for side in [back.perp(), -back.perp()] {
+        let head = tip + back + side * 0.5;
+        draw_line(tip.x, tip.y, head.x, head.y, 2., WHITE);
+    }
+}
+
+// cell (x, y) is drawn at screen position (x + offset) * zoom
+#[derive(Clone, Copy)]
+struct Camera {
+    offset: Vec2,
+    zoom: f32,
+}
+
+impl Camera {
+    fn new() -> Camera {
+        Camera {
+            offset: Vec2::ZERO,
+            zoom: 1.,
+        }
+    }
+    // keeps the cell under the screen point at in place
+    fn zoom_at(&mut self, factor: f32, at: Vec2) {
+        let anchor = at / self.zoom - self.offset;
+        self.zoom = (self.zoom * factor).clamp(0.25, 16.);
+        self.offset = at / self.zoom - anchor;
+    }
+    // scroll zooms around the cursor and pinching around the fingers, middle drag
+    // or a two finger drag pans
+    fn update(&mut self, pan: Vec2, pinch: Option<(f32, Vec2)>) {
+        let (_, wheel) = mouse_wheel();
+        if wheel != 0. {
+            let factor = match wheel > 0. {
+                true => 1.1,
+                false => 0.9,
+            };
+            self.zoom_at(factor, Vec2::from(mouse_position()));
+        }
+        if let Some((ratio, mid)) = pinch {
+            self.zoom_at(ratio, mid);
+        }
+        self.offset += pan / self.zoom;
+    }
+    fn cell_to_screen(&self, x: usize, y: usize) -> Vec2 {
+        (vec2(x as f32, y as f32) + self.offset) * self.zoom
+    }
+    fn dest(&self, w: usize, h: usize) -> Rect {
+        Rect::new(
+            self.offset.x * self.zoom,
+            self.offset.y * self.zoom,
+            w as f32 * self.zoom,
+            h as f32 * self.zoom,
+        )
+    }
+}
+
+// zoom from which cells are drawn one rectangle each, and from which they get borders
+const DIRECT_ZOOM: f32 = 2.;
+const BORDER_ZOOM: f32 = 6.;
+
+// pointy-top hexagon around (cx, cy) with corners r away
+fn draw_hexagon(cx: f32, cy: f32, r: f32, color: Color) {
+    let c = vec2(cx, cy);
+    let corner = |i: usize| {
+        c + Vec2::from_angle(std::f32::consts::FRAC_PI_3 * i as f32 + std::f32::consts::FRAC_PI_6)
+            * r
+    };
+    for i in 0..6 {
+        draw_triangle(c, corner(i), corner(i + 1), color);
+    }
+}
+
+// draws only the cells inside viewport (x, y, w, h in screen pixels) straight
+// from image, empty cells are left to the black background. Hexagonal cells
+// are drawn tall enough for the rows to touch
+fn draw_cells_direct(
+    image: &Image,
+    camera: &Camera,
+    viewport: (f32, f32, f32, f32),
+    grid: GridType,
+) {
+    let (vx, vy, vw, vh) = viewport;
+    let (w, h) = (image.width(), image.height());
+    let zoom = camera.zoom;
+    let p0 = (vec2(vx, vy) / zoom - camera.offset)
+        .floor()
+        .max(Vec2::ZERO);
+    let p1 = (vec2(vx + vw, vy + vh) / zoom - camera.offset)
+        .ceil()
+        .min(vec2(w as f32, h as f32));
+    if p1.x <= p0.x || p1.y <= p0.y {
+        return;
+    }
+    let (x0, y0, x1, y1) = (p0.x as usize, p0.y as usize, p1.x as usize, p1.y as usize);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let c = image.get_pixel(x as u32, y as u32);
+            if c.r == 0. && c.g == 0. && c.b == 0. {
+                continue;
+            }
+            let p = camera.cell_to_screen(x, y);
+            match grid {
+                GridType::Square => draw_rectangle(p.x, p.y, zoom, zoom, c),
+                GridType::Hexagonal => {
+                    let shift = 0.5 * (y % 2) as f32;
+                    draw_hexagon(
+                        p.x + zoom * (0.5 + shift),
+                        p.y + zoom * 0.5,
+                        zoom * 2. / 3.,
+                        c,
+                    );
+                }
+            }
+        }
+    }
+    // one line per row and column instead of an outline per cell
+    if zoom >= BORDER_ZOOM && grid == GridType::Square {
+        let a = camera.cell_to_screen(x0, y0);
+        let b = camera.cell_to_screen(x1, y1);
+        for x in x0..=x1 {
+            let sx = camera.cell_to_screen(x, y0).x;
+            draw_line(sx, a.y, sx, b.y, 1., DARKGRAY);
+        }
+        for y in y0..=y1 {
+            let sy = camera.cell_to_screen(x0, y).y;
+            draw_line(a.x, sy, b.x, sy, 1., DARKGRAY);
+        }
+    }
+}
+
+const MINIMAP_W: usize = 200;
+const MINIMAP_H: usize = 150;
+
+// nearest-neighbor overview of the whole field, refreshed every 5 frames
+struct MiniMap {
+    image: Image,
+    texture: Texture2D,
+    frame: usize,
+}
+
+impl MiniMap {
+    fn new() -> MiniMap {
+        let image = Image::gen_image_color(MINIMAP_W as u16, MINIMAP_H as u16, BLACK);
+        let texture = Texture2D::from_image(&image);
+        texture.set_filter(FilterMode::Nearest);
+        MiniMap {
+            image,
+            texture,
+            frame: 0,
+        }
+    }
+    fn update(&mut self, src: &Image) {
+        self.frame += 1;
+        if self.frame % 5 != 1 {
+            return;
+        }
+        let (w, h) = (src.width(), src.height());
+        for j in 0..MINIMAP_H {
+            for i in 0..MINIMAP_W {
+                let c = src.get_pixel((i * w / MINIMAP_W) as u32, (j * h / MINIMAP_H) as u32);
+                self.image.set_pixel(i as u32, j as u32, c);
+            }
+        }
+        self.texture.update(&self.image);
+    }
+    fn draw(&self, camera: &Camera, w: usize, h: usize) {
+        let (mw, mh) = (MINIMAP_W as f32, MINIMAP_H as f32);
+        let x0 = screen_width() - mw - 10.;
+        let y0 = screen_height() - mh - 10.;
+        draw_rectangle(
+            x0 - 4.,
+            y0 - 4.,
+            mw + 8.,
+            mh + 8.,
+            Color::new(0., 0., 0., 0.6),
+        );
+        draw_texture_ex(
+            self.texture,
+            x0,
+            y0,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(vec2(mw, mh)),
+                ..Default::default()
+            },
+        );
+        // visible cells, clipped to the field
+        let scale = vec2(mw / w as f32, mh / h as f32);
+        let p0 = (-camera.offset).max(Vec2::ZERO);
+        let p1 = (vec2(screen_width(), screen_height()) / camera.zoom - camera.offset)
+            .min(vec2(w as f32, h as f32));
+        if p1.x > p0.x && p1.y > p0.y {
+            let (a, b) = (p0 * scale, p1 * scale);
+            draw_rectangle_lines(x0 + a.x, y0 + a.y, b.x - a.x, b.y - a.y, 1., WHITE);
+        }
+    }
+}
+
+const TAP_SECONDS: f32 = 0.3;
+const TAP_SLOP: f32 = 20.;
+
+enum TwoFingerGesture {
+    // the fingers just came down, or came back after a stall
+    Start,
+    // zoom by ratio around mid and pan along with it
+    Pinch { ratio: f32, mid: Vec2, pan: Vec2 },
+}
+
+// prev is where the fingers were last frame, None as they come down; dt is the
+// time since then, positions older than a tap are stale and start over
+fn classify_two_finger_gesture(
+    prev: Option<[Vec2; 2]>,
+    curr: [Vec2; 2],
+    dt: f32,
+) -> TwoFingerGesture {
+    let Some(prev) = prev.filter(|_| dt < TAP_SECONDS) else {
+        return TwoFingerGesture::Start;
+    };
+    let (before, after) = (prev[0].distance(prev[1]), curr[0].distance(curr[1]));
+    let mid = (curr[0] + curr[1]) / 2.;
+    TwoFingerGesture::Pinch {
+        ratio: match before > 1. {
+            true => after / before,
+            false => 1.,
+        },
+        mid,
+        pan: mid - (prev[0] + prev[1]) / 2.,
+    }
+}
+
+// fingers lifted quickly near where they came down, opens the controls
+fn is_two_finger_tap(start: [Vec2; 2], end: [Vec2; 2], held: f32) -> bool {
+    let still = start[0].distance(end[0]) < TAP_SLOP && start[1].distance(end[1]) < TAP_SLOP;
+    still && held < TAP_SECONDS
+}
+
+fn screen_to_cell(screen_pos: Vec2, camera: &Camera, w: usize, h: usize) -> Option<(usize, usize)> {
+    let p = screen_pos / camera.zoom - camera.offset;
+    if p.x < 0. || p.y < 0. || p.x >= w as f32 || p.y >= h as f32 {
+        return None;
+    }
+    Some((p.x as usize, p.y as usize))
+}
+
+struct DirtyRect {
+    min_x: usize,
+    max_x: usize,
+    min_y: usize,
+    max_y: usize,
+}
+
+impl DirtyRect {
+    fn new() -> DirtyRect {
+        DirtyRect {
+            min_x: usize::MAX,
+            max_x: 0,
+            min_y: usize::MAX,
+            max_y: 0,
+        }
+    }
+    fn add(&mut self, x: usize, y: usize) {
+        self.min_x = self.min_x.min(x);
+        self.max_x = self.max_x.max(x);
+        self.min_y = self.min_y.min(y);
+        self.max_y = self.max_y.max(y);
+    }
+    fn add_all(&mut self, w: usize, h: usize) {
+        self.add(0, 0);
+        self.add(w - 1, h - 1);
+    }
+    fn add_disk(&mut self, cx: usize, cy: usize, r: usize, w: usize, h: usize) {
+        self.add(cx.saturating_sub(r), cy.saturating_sub(r));
+        self.add((cx + r).min(w - 1), (cy + r).min(h - 1));
+    }
+    // (x, y, w, h) of the changed area, None if nothing changed
+    fn rect(&self) -> Option<(usize, usize, usize, usize)> {
+        if self.min_x > self.max_x {
+            return None;
+        }
+        Some((
+            self.min_x,
+            self.min_y,
+            self.max_x - self.min_x + 1,
+            self.max_y - self.min_y + 1,
+        ))
+    }
+}
+
+fn set_pixel_dirty(img: &mut Image, dirty: &mut DirtyRect, x: usize, y: usize, c: Color) {
+    img.set_pixel(x as u32, y as u32, c);
+    dirty.add(x, y);
+}
+
+// upload only the changed part of the image, returns the number of pixels sent
+fn upload_dirty(texture: Texture2D, image: &Image, dirty: &DirtyRect) -> usize {
+    let Some((x, y, w, h)) = dirty.rect() else {
+        return 0;
+    };
+    if w == image.width() && h == image.height() {
+        texture.update(image);
+    } else {
+        let sub = image.sub_image(Rect::new(x as f32, y as f32, w as f32, h as f32));
+        texture.update_part(&sub, x as i32, y as i32, w as i32, h as i32);
+    }
+    w * h
+}
+
+const ROAD_COLOR: Color = DARKGRAY;
+const WATER_COLOR: Color = Color::new(0.0, 0.3, 0.8, 1.0);
+
+// roads and water never hold trees or burn
+fn is_burnable(x: usize, y: usize, water: &SparseCellField, roads: &SparseCellField) -> bool {
+    !water.get(x, y) && !roads.get(x, y)
+}
+
+// marks the cells within width of each segment as road and paints them
+fn rasterize_roads(
+    segs: &[(usize, usize, usize, usize)],
+    width: usize,
+    road_field: &mut SparseCellField,
+    img: &mut Image,
+) {
+    let (w, h) = (road_field.w, road_field.h);
+    for &(x0, y0, x1, y1) in segs {
+        for_line(x0, y0, x1, y1, |cx, cy| {
+            for_disk(cx, cy, width, w, h, |x, y| {
+                road_field.set(x, y);
+                img.set_pixel(x as u32, y as u32, ROAD_COLOR);
+            });
+        });
+    }
+}
+
+fn draw_firebreak(cx: usize, cy: usize, radius: usize, cf: &mut CellField, img: &mut Image) {
+    let (w, h) = (cf.w, cf.h);
+    for_disk(cx, cy, radius, w, h, |x, y| {
+        cf.clr(x, y);
+        img.set_pixel(x as u32, y as u32, BLACK);
+    });
+}
+
+const TANKER_DROPS: usize = 5;
+// steps the ground under a drop stays wet
+const TANKER_WET_STEPS: usize = 300;
+
+// puts out every fire within r of (cx, cy) and soaks the disk
+#[allow(clippy::too_many_arguments)]
+fn apply_tanker_drop(
+    fires: &mut Vec<Fire>,
+    fire_field: &mut CellField,
+    wet_field: &mut CellField,
+    img: &mut Image,
+    cf: &CellField,
+    cx: usize,
+    cy: usize,
+    r: usize,
+    alive: Color,
+) {
+    fires.retain(|f| {
+        if f.x.abs_diff(cx).pow(2) + f.y.abs_diff(cy).pow(2) > r * r {
+            return true;
+        }
+        fire_field.clr(f.x, f.y);
+        let c = match cf.get(f.x, f.y) {
+            true => alive,
+            false => BLACK,
+        };
+        img.set_pixel(f.x as u32, f.y as u32, c);
+        false
+    });
+    for_disk(cx, cy, r, cf.w, cf.h, |x, y| wet_field.set(x, y));
+}
+
+// returns the cell of the fire put out, if there was any
+fn extinguish_nearest(
+    cx: usize,
+    cy: usize,
+    fires: &mut Vec<Fire>,
+    cf: &CellField,
+    img: &mut Image,
+    alive: Color,
+) -> Option<(usize, usize)> {
+    let (i, f) = fires
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, f)| f.x.abs_diff(cx).pow(2) + f.y.abs_diff(cy).pow(2))?;
+    let (x, y) = (f.x, f.y);
+    fires.swap_remove(i);
+    let c = match cf.get(x, y) {
+        true => alive,
+        false => BLACK,
+    };
+    img.set_pixel(x as u32, y as u32, c);
+    Some((x, y))
+}
+
+fn init_forest(
+    w: usize,
+    h: usize,
+    config: &SimConfig,
+    alive: Color,
+) -> (CellField, CellField, Image) {
+    let mut cellfield = CellField::new(w, h);
+    let mut wet_field = CellField::new(w, h);
+    let mut image = Image::gen_image_color(w as u16, h as u16, BLACK);
+
+    // trees where the noise is above the threshold, shifted so each start differs
+    let offset = match config.use_perlin_init {
+        false => None,
+        true => Some((rand::gen_range(0., 1e4), rand::gen_range(0., 1e4))),
+    };
+    for y in 0..h {
+        for x in 0..w {
+            let tree = match offset {
+                None => rand::gen_range(0.0f32, 1.0) < config.initial_density,
+                Some((ox, oy)) => {
+                    let n = noise::fbm2(x as f32 + ox, y as f32 + oy, config.noise_scale, 4, 0.5);
+                    n > config.noise_threshold
+                }
+            };
+            if tree {
+                cellfield.set(x, y);
+                image.set_pixel(x as u32, y as u32, alive);
+                if rand::gen_range(0.0f32, 1.0) < 10f32.powf(config.logwetness) {
+                    wet_field.set(x, y);
+                }
+            }
+        }
+    }
+    (cellfield, wet_field, image)
+}
+
+fn paint_image(cf: &CellField, fires: &[Fire], config: &SimConfig, alive: Color) -> Image {
+    let mut image = Image::gen_image_color(cf.w as u16, cf.h as u16, BLACK);
+    for (x, y) in cf.iter_set() {
+        image.set_pixel(x as u32, y as u32, alive);
+    }
+    for f in fires {
+        image.set_pixel(f.x as u32, f.y as u32, fire_color(f, config));
+    }
+    image
+}
+
+// grids with more cells than this export 4x4 blocks as one rect each
+const SVG_MAX_CELLS: usize = 1000 * 1000;
+const SVG_BLOCK: usize = 4;
+
+fn svg_hex(c: Color) -> String {
+    let b = |v: f32| (v.clamp(0., 1.) * 255.).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", b(c.r), b(c.g), b(c.b))
+}
+
+// the trees in their colors from img, grouped by color, and the fires on top
+// in fire_colors. On large grids a block at least half full of trees becomes
+// one rect in their mean color
+fn export_svg(cf: &CellField, img: &Image, fires: &[Fire], fire_colors: &[Color]) -> String {
+    let (w, h) = (cf.w, cf.h);
+    let size = match w * h > SVG_MAX_CELLS {
+        false => 1,
+        true => SVG_BLOCK,
+    };
+    let mut groups: std::collections::BTreeMap<String, Vec<(usize, usize)>> = Default::default();
+    for by in (0..h).step_by(size) {
+        for bx in (0..w).step_by(size) {
+            let (mut n, mut cells, mut sum) = (0, 0, [0f32; 3]);
+            for y in by..(by + size).min(h) {
+                for x in bx..(bx + size).min(w) {
+                    cells += 1;
+                    if cf.get(x, y) {
+                        let c = img.get_pixel(x as u32, y as u32);
+                        sum = [sum[0] + c.r, sum[1] + c.g, sum[2] + c.b];
+                        n += 1;
+                    }
+                }
+            }
+            if n == 0 || n * 2 < cells {
+                continue;
+            }
+            let n = n as f32;
+            let c = Color::new(sum[0] / n, sum[1] / n, sum[2] / n, 1.);
+            groups.entry(svg_hex(c)).or_default().push((bx, by));
+        }
+    }
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {w} {h}\" \
+         width=\"{w}\" height=\"{h}\" shape-rendering=\"crispEdges\">\n\
+         <rect width=\"{w}\" height=\"{h}\" fill=\"#000000\"/>\n"
+    );
+    for (fill, rects) in &groups {
+        svg += &format!("<g fill=\"{}\">\n", fill);
+        for (x, y) in rects {
+            svg += &format!("<rect x=\"{x}\" y=\"{y}\" width=\"{size}\" height=\"{size}\"/>\n");
+        }
+        svg += "</g>\n";
+    }
+    for (f, c) in fires.iter().zip(fire_colors) {
+        svg += &format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"1\" height=\"1\" fill=\"{}\"/>\n",
+            f.x,
+            f.y,
+            svg_hex(*c)
+        );
+    }
+    svg += "</svg>\n";
+    svg
+}
+
+#[cfg(feature = "multistate")]
+fn stages_from(cf: &CellField) -> PackedField<2> {
+    let mut stages = PackedField::new(cf.w, cf.h);
+    for (x, y) in cf.iter_set() {
+        stages.set(x, y, MATURE);
+    }
+    stages
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BrushMode {
+    Fire,
+    Tree,
+    Erase,
+    Rect,
+    Flood,
+    Water,
+}
+
+const MAX_FLOOD_CELLS: usize = 2_000_000;
+
+// plants trees on the 4-connected empty cells around the seed, returns how many
+fn flood_fill_trees(
+    cf: &mut CellField,
+    img: &mut Image,
+    seed_x: usize,
+    seed_y: usize,
+    color: Color,
+) -> usize {
+    if cf.get(seed_x, seed_y) {
+        return 0;
+    }
+    let mut queue = VecDeque::from([(seed_x, seed_y)]);
+    cf.set(seed_x, seed_y);
+    let mut filled = 0;
+    while let Some((x, y)) = queue.pop_front() {
+        img.set_pixel(x as u32, y as u32, color);
+        filled += 1;
+        if filled + queue.len() >= MAX_FLOOD_CELLS {
+            continue;
+        }
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+            if nx < 0 || ny < 0 || nx >= cf.w as i32 || ny >= cf.h as i32 {
+                continue;
+            }
+            let (nx, ny) = (nx as usize, ny as usize);
+            if !cf.get(nx, ny) {
+                cf.set(nx, ny);
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    filled
+}
+
+// sets (seed_x, seed_y) and every tree 4-connected to it on fire, the seed
+// first, returns how many cells caught. Their number is the size of the seed's
+// percolation cluster
+fn flash_fire(seed_x: usize, seed_y: usize, cf: &mut CellField, fires: &mut Vec<Fire>) -> usize {
+    let mut queue = VecDeque::from([(seed_x, seed_y)]);
+    cf.clr(seed_x, seed_y);
+    let mut ignited = 0;
+    while let Some((x, y)) = queue.pop_front() {
+        fires.push(Fire::new(x, y, 1.0));
+        ignited += 1;
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+            if nx < 0 || ny < 0 || nx >= cf.w as i32 || ny >= cf.h as i32 {
+                continue;
+            }
+            let (nx, ny) = (nx as usize, ny as usize);
+            if cf.get(nx, ny) {
+                cf.clr(nx, ny);
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    ignited
+}
+
+// corner cell where the current rectangle drag started, and the trees of the
+// last rectangle copied
+struct RectTool {
+    anchor: Option<(usize, usize)>,
+    clip: Option<CellField>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cellfield_fill_rect(
+    cf: &mut CellField,
+    img: &mut Image,
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+    set: bool,
+    color: Color,
+) {
+    match set {
+        true => cf.fill_rect(x0, y0, x1, y1),
+        false => cf.clear_rect(x0, y0, x1, y1),
+    }
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            img.set_pixel(x as u32, y as u32, color);
+        }
+    }
+}
+
+struct BrushTool {
+    mode: BrushMode,
+    radius: usize,
+}
+
+fn apply_brush(
+    cx: usize,
+    cy: usize,
+    tool: &BrushTool,
+    cf: &mut CellField,
+    fires: &mut Vec<Fire>,
+    img: &mut Image,
+    alive: Color,
+) {
+    let (w, h) = (cf.w, cf.h);
+    match tool.mode {
+        BrushMode::Fire => for_disk(cx, cy, tool.radius, w, h, |x, y| {
+            if cf.get(x, y) {
+                cf.clr(x, y);
+                fires.push(Fire::new(x, y, 1.0));
+                img.set_pixel(x as u32, y as u32, Color::new(1., 0., 0., 1.0));
+            }
+        }),
+        BrushMode::Tree => for_disk(cx, cy, tool.radius, w, h, |x, y| {
+            cf.set(x, y);
+            img.set_pixel(x as u32, y as u32, alive);
+        }),
+        BrushMode::Erase => draw_firebreak(cx, cy, tool.radius, cf, img),
+        // rectangles and flood fills are clicks, not strokes
+        // water needs the whole state, see SimState::paint_water
+        BrushMode::Rect | BrushMode::Flood | BrushMode::Water => {}
+    }
+}
+
+fn resize_image(image: &Image, w: usize, h: usize) -> Image {
+    let mut resized = Image::gen_image_color(w as u16, h as u16, BLACK);
+    for y in 0..image.height().min(h) {
+        for x in 0..image.width().min(w) {
+            resized.set_pixel(x as u32, y as u32, image.get_pixel(x as u32, y as u32));
+        }
+    }
+    resized
+}
+
+fn resize_grid<T: Copy + Default>(arr: &[T], old_w: usize, w: usize, h: usize) -> Vec<T> {
+    let mut resized = vec![T::default(); w * h];
+    for (y, row) in arr.chunks_exact(old_w).take(h).enumerate() {
+        let n = old_w.min(w);
+        resized[y * w..y * w + n].copy_from_slice(&row[..n]);
+    }
+    resized
+}
+
+const NGH: [[i32; 2]; 8] = [
+    [-1, 0],
+    [1, 0],
+    [0, -1],
+    [0, 1],
+    [-1, -1],
+    [-1, 1],
+    [1, -1],
+    [1, 1],
+];
+
+// neighbor offsets on the hexagonal grid for even and odd rows
+const HEX_NGH: [[[i32; 2]; 6]; 2] = [
+    [[1, 0], [-1, 0], [0, 1], [0, -1], [-1, 1], [-1, -1]],
+    [[1, 0], [-1, 0], [0, 1], [0, -1], [1, 1], [1, -1]],
+];
+
+// the neighbors of (x, y) inside the grid, row_parity is y % 2
+fn hex_neighbors(
+    x: usize,
+    y: usize,
+    row_parity: u8,
+    w: usize,
+    h: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    HEX_NGH[row_parity as usize & 1]
+        .iter()
+        .filter_map(move |d| {
+            let (nx, ny) = (x as i32 + d[0], y as i32 + d[1]);
+            match nx >= 0 && nx < w as i32 && ny >= 0 && ny < h as i32 {
+                false => None,
+                true => Some((nx as usize, ny as usize)),
+            }
+        })
+}
+
+// set neighbors of (x, y) on the hexagonal grid
+fn hex_count(cf: &CellField, x: usize, y: usize, torus: bool) -> u8 {
+    let (w, h) = (cf.w as i32, cf.h as i32);
+    let n = match torus {
+        false => hex_neighbors(x, y, (y % 2) as u8, cf.w, cf.h)
+            .filter(|&(nx, ny)| cf.get(nx, ny))
+            .count(),
+        true => HEX_NGH[y % 2]
+            .iter()
+            .filter(|d| {
+                let nx = (x as i32 + d[0]).rem_euclid(w);
+                let ny = (y as i32 + d[1]).rem_euclid(h);
+                cf.get(nx as usize, ny as usize)
+            })
+            .count(),
+    };
+    n as u8
+}
+
+pub struct SimState {
+    config: SimConfig,
+    cellfield: CellField,
+    wet_field: CellField,
+    char_field: CharField,
+    seedling_field: CellField,
+    // steps until each burned out cell can catch fire again, row-major
+    cooldown: Vec<u16>,
+    // no cooldown entry is above this, 0 skips the scan
+    cooldown_max: u16,
+    // steps each tree has stood, row-major
+    age_field: Vec<u16>,
+    // index into SPECIES, NO_SPECIES where there is no tree
+    species_field: Vec<u8>,
+    // times each cell burned out, cleared only on request
+    burn_count: Vec<u32>,
+    // step the burn counts started from
+    burns_since: usize,
+    event_log: VecDeque<(usize, SimEvent)>,
+    // cells holding an entry of newfires, rebuilt every step
+    fire_field: CellField,
+    // permanent roads and water, nothing grows on them, mostly empty
+    road_field: SparseCellField,
+    water_field: SparseCellField,
+    // percolation band of the density at the last step, "" before the first
+    density_band: &'static str,
+    #[cfg(feature = "multistate")]
+    stages: PackedField<2>,
+    rained: VecDeque<(usize, usize, usize)>,
+    // cells soaked by tanker drops, with the step they dry out at
+    doused: VecDeque<(usize, usize, usize)>,
+    last_rain: Option<usize>,
+    drought: DroughtState,
+    fires: FireBuffer,
+    image: Image,
+    dirty: DirtyRect,
+    stats: SimStats,
+    frno: usize,
+    colorphase: f32,
+    fireproc: PoissonProcess2D,
+    treeproc: PoissonProcess,
+    dryproc: PoissonProcess,
+    alive_color: Color,
+}
+
+impl SimState {
+    pub fn new(config: SimConfig, w: usize, h: usize) -> SimState {
+        let alive_color = Color::new(0.0, 0.5, 0.0, 1.0);
+        let (cellfield, wet_field, image) = init_forest(w, h, &config, alive_color);
+        SimState {
+            #[cfg(feature = "multistate")]
+            stages: stages_from(&cellfield),
+            config,
+            cellfield,
+            wet_field,
+            char_field: CharField::new(w, h),
+            seedling_field: CellField::new(w, h),
+            cooldown: vec![0; w * h],
+            cooldown_max: 0,
+            age_field: vec![0; w * h],
+            species_field: vec![NO_SPECIES; w * h],
+            burn_count: vec![0; w * h],
+            burns_since: 0,
+            event_log: VecDeque::new(),
+            fire_field: CellField::new(w, h),
+            road_field: SparseCellField::new(w, h),
+            water_field: SparseCellField::new(w, h),
+            density_band: "",
+            rained: VecDeque::new(),
+            doused: VecDeque::new(),
+            last_rain: None,
+            drought: DroughtState::new(),
+            fires: FireBuffer::new(),
+            image,
+            dirty: DirtyRect::new(),
+            stats: SimStats::new(),
+            frno: 0,
+            colorphase: 0.,
+            fireproc: PoissonProcess2D::uniform(w, h),
+            treeproc: PoissonProcess::new(),
+            dryproc: PoissonProcess::new(),
+            alive_color,
+        }
+    }
+    // the cells as drawn, what the texture is updated from
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+    fn resize(&mut self, w: usize, h: usize) {
+        self.age_field = resize_grid(&self.age_field, self.cellfield.w, w, h);
+        self.cooldown = resize_grid(&self.cooldown, self.cellfield.w, w, h);
+        self.species_field = resize_grid(&self.species_field, self.cellfield.w, w, h);
+        self.burn_count = resize_grid(&self.burn_count, self.cellfield.w, w, h);
+        self.fire_field = CellField::new(w, h);
+        self.road_field.resize(w, h);
+        self.water_field.resize(w, h);
+        self.fireproc.resize(w, h);
+        self.image = resize_image(&self.image, w, h);
+        self.cellfield.resize(w, h);
+        self.wet_field.resize(w, h);
+        self.char_field.resize(w, h);
+        self.seedling_field.resize(w, h);
+        #[cfg(feature = "multistate")]
+        self.stages.resize(w, h);
+        self.rained.retain(|&(x, y, _)| x < w && y < h);
+        self.doused.retain(|&(x, y, _)| x < w && y < h);
+        for f in self.fires.front_mut() {
+            f.x = f.x.min(w - 1);
+            f.y = f.y.min(h - 1);
+        }
+        self.dirty.add_all(w, h);
+    }
+    fn kill_fires(&mut self) {
+        for f in self.fires.front_mut().drain(..) {
+            let c = match self.cellfield.get(f.x, f.y) {
+                true => self.alive_color,
+                false => BLACK,
+            };
+            self.image.set_pixel(f.x as u32, f.y as u32, c);
+            self.dirty.add(f.x, f.y);
+        }
+    }
+    // clears whatever grew or was loaded onto roads and water and paints them
+    // over, after anything that replaces the forest or the image
+    fn apply_barriers(&mut self) {
+        let w = self.image.width();
+        let open = !&(&self.road_field.to_dense() | &self.water_field.to_dense());
+        self.cellfield &= &open;
+        self.seedling_field &= &open;
+        for (field, c) in [
+            (&self.road_field, ROAD_COLOR),
+            (&self.water_field, WATER_COLOR),
+        ] {
+            for (x, y) in field.iter_set() {
+                self.char_field.arr[y * w + x] = 0;
+                self.cooldown[y * w + x] = 0;
+                #[cfg(feature = "multistate")]
+                self.stages.set(x, y, EMPTY);
+                self.image.set_pixel(x as u32, y as u32, c);
+            }
+        }
+        let (water, roads) = (&self.water_field, &self.road_field);
+        self.fires
+            .front_mut()
+            .retain(|f| is_burnable(f.x, f.y, water, roads));
+        self.dirty.add_all(w, self.image.height());
+    }
+    // the same for the cells within r of (cx, cy), after a brush stroke
+    fn repave(&mut self, cx: usize, cy: usize, r: usize) {
+        let (w, h) = (self.cellfield.w, self.cellfield.h);
+        for_disk(cx, cy, r, w, h, |x, y| {
+            let c = match (self.water_field.get(x, y), self.road_field.get(x, y)) {
+                (true, _) => WATER_COLOR,
+                (false, true) => ROAD_COLOR,
+                (false, false) => return,
+            };
+            self.cellfield.clr(x, y);
+            self.image.set_pixel(x as u32, y as u32, c);
+        });
+    }
+    // turns the cells within r of (cx, cy) into water, putting out their fires
+    fn paint_water(&mut self, cx: usize, cy: usize, r: usize) {
+        let (w, h) = (self.cellfield.w, self.cellfield.h);
+        for_disk(cx, cy, r, w, h, |x, y| self.water_field.set(x, y));
+        let water = &self.water_field;
+        self.fires.front_mut().retain(|f| !water.get(f.x, f.y));
+        self.repave(cx, cy, r);
+    }
+    // sets the forests of the trees within r of (cx, cy) on fire, all of them
+    fn flash_disk(&mut self, cx: usize, cy: usize, r: usize) {
+        let (w, h) = (self.cellfield.w, self.cellfield.h);
+        let mut flashed = Vec::new();
+        for_disk(cx, cy, r, w, h, |x, y| {
+            if self.cellfield.get(x, y) {
+                flash_fire(x, y, &mut self.cellfield, &mut flashed);
+            }
+        });
+        let red = Color::new(1., 0., 0., 1.0);
+        for f in &flashed {
+            set_pixel_dirty(&mut self.image, &mut self.dirty, f.x, f.y, red);
+        }
+        self.fires.front_mut().extend(flashed);
+    }
+    // the trees of clip with its top left at (x0, y0), fires keep burning
+    fn paste(&mut self, clip: &CellField, x0: usize, y0: usize) {
+        let (w, h) = (self.cellfield.w, self.cellfield.h);
+        self.cellfield.blit(clip, x0, y0);
+        for y in y0..(y0 + clip.h).min(h) {
+            for x in x0..(x0 + clip.w).min(w) {
+                let c = match self.cellfield.get(x, y) {
+                    false => BLACK,
+                    true => self.alive_color,
+                };
+                self.image.set_pixel(x as u32, y as u32, c);
+            }
+        }
+        self.apply_barriers();
+    }
+    fn tanker_drop(&mut self, cx: usize, cy: usize, r: usize) {
+        apply_tanker_drop(
+            self.fires.front_mut(),
+            &mut self.fire_field,
+            &mut self.wet_field,
+            &mut self.image,
+            &self.cellfield,
+            cx,
+            cy,
+            r,
+            self.alive_color,
+        );
+        let (w, h) = (self.cellfield.w, self.cellfield.h);
+        let dry = self.frno + TANKER_WET_STEPS;
+        for_disk(cx, cy, r, w, h, |x, y| self.doused.push_back((x, y, dry)));
+        self.dirty.add_disk(cx, cy, r, w, h);
+    }
+    // the trees are left alone, a fire on empty ground burns all the same
+    fn replace_fires(&mut self, fires: Vec<Fire>) {
+        self.kill_fires();
+        for f in &fires {
+            let c = fire_color(f, &self.config);
+            set_pixel_dirty(&mut self.image, &mut self.dirty, f.x, f.y, c);
+        }
+        *self.fires.front_mut() = fires;
+    }
+    // burned ground is cleared too, so nothing regrows by itself
+    fn clear_trees(&mut self) {
+        let (w, h) = (self.image.width(), self.image.height());
+        self.cellfield.arr.fill(0);
+        self.seedling_field.arr.fill(0);
+        self.char_field = CharField::new(w, h);
+        self.cooldown.fill(0);
+        self.cooldown_max = 0;
+        self.age_field.fill(0);
+        self.species_field.fill(NO_SPECIES);
+        #[cfg(feature = "multistate")]
+        {
+            self.stages = PackedField::new(w, h);
+        }
+        self.fires.front_mut().clear();
+        self.image = Image::gen_image_color(w as u16, h as u16, BLACK);
+        self.apply_barriers();
+    }
+    // seedlings keep their own color
+    fn repaint_trees(&mut self) {
+        let w = self.image.width();
+        for (x, y) in self.cellfield.iter_set() {
+            if self.seedling_field.get(x, y) {
+                continue;
+            }
+            let c = match self.config.age_color {
+                false => species_color(self.species_field[y * w + x], self.colorphase),
+                true => tree_age_color(self.age_field[y * w + x]),
+            };
+            self.image.set_pixel(x as u32, y as u32, c);
+        }
+        self.dirty.add_all(w, self.image.height());
+    }
+    fn image_without_fires(&self) -> Image {
+        let mut base = self.image.clone();
+        for f in self.fires.read_front() {
+            let c = match self.cellfield.get(f.x, f.y) {
+                true => self.alive_color,
+                false => BLACK,
+            };
+            base.set_pixel(f.x as u32, f.y as u32, c);
+        }
+        base
+    }
+    // base image with the current fires drawn on top, for replays
+    fn paint_fires_over(&mut self, base: &Image) {
+        self.image = base.clone();
+        for f in self.fires.read_front() {
+            self.image
+                .set_pixel(f.x as u32, f.y as u32, fire_color(f, &self.config));
+        }
+        self.dirty.add_all(self.image.width(), self.image.height());
+    }
+    // replaces the forest and fires, the rest of the state starts over
+    // the trees live in mapped from now on, unless it is empty they replace the
+    // forest
+    fn map_trees(&mut self, mapped: CellField) {
+        let current = std::mem::replace(&mut self.cellfield, mapped);
+        match self.cellfield.count_set() {
+            0 => self.cellfield.replace(current),
+            _ => {
+                let (trees, fires) = (self.cellfield.clone(), self.fires.read_front().to_vec());
+                self.import(trees, fires);
+            }
+        }
+    }
+    fn import(&mut self, cellfield: CellField, fires: Vec<Fire>) {
+        let (w, h) = (self.image.width(), self.image.height());
+        self.cellfield.replace(cellfield);
+        *self.fires.front_mut() = fires;
+        self.image = paint_image(
+            &self.cellfield,
+            self.fires.read_front(),
+            &self.config,
+            self.alive_color,
+        );
+        self.char_field = CharField::new(w, h);
+        self.seedling_field = CellField::new(w, h);
+        self.cooldown = vec![0; w * h];
+        self.cooldown_max = 0;
+        self.age_field = vec![0; w * h];
+        self.species_field = vec![NO_SPECIES; w * h];
+        #[cfg(feature = "multistate")]
+        {
+            self.stages = stages_from(&self.cellfield);
+        }
+        self.apply_barriers();
+    }
+    // entries outside the grid are skipped with a warning
+    fn ignite_clusters(&mut self, clusters: &[(usize, usize, usize)]) {
+        let (w, h) = (self.cellfield.w, self.cellfield.h);
+        let before = self.fires.read_front().len();
+        for &(cx, cy, r) in clusters {
+            if cx >= w || cy >= h || r >= w.max(h) {
+                warn!(
+                    "skipping cluster {} {} {} outside the {}x{} grid",
+                    cx, cy, r, w, h
+                );
+                continue;
+            }
+            ignite_cluster(self.fires.front_mut(), &mut self.cellfield, cx, cy, r);
+        }
+        for f in &self.fires.read_front()[before..] {
+            let c = fire_color(f, &self.config);
+            set_pixel_dirty(&mut self.image, &mut self.dirty, f.x, f.y, c);
+        }
+    }
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            config: self.config.clone(),
+            cellfield: self.cellfield.clone(),
+            wet_field: self.wet_field.clone(),
+            fires: self.fires.read_front().to_vec(),
+            frno: self.frno,
+            colorphase: self.colorphase,
+        }
+    }
+    // regrowth and rain timers are not saved, they restart empty
+    fn restore(&mut self, saved: Snapshot) {
+        let (w, h) = (self.image.width(), self.image.height());
+        self.config = saved.config;
+        let mut trees = saved.cellfield;
+        trees.resize(w, h);
+        self.cellfield.replace(trees);
+        self.wet_field = saved.wet_field;
+        self.wet_field.resize(w, h);
+        *self.fires.front_mut() = saved.fires;
+        self.fires.front_mut().retain(|f| f.x < w && f.y < h);
+        self.frno = saved.frno;
+        self.colorphase = saved.colorphase;
+        self.rained.clear();
+        self.doused.clear();
+        self.last_rain = None;
+        self.drought = DroughtState::new();
+        self.image = paint_image(
+            &self.cellfield,
+            self.fires.read_front(),
+            &self.config,
+            self.alive_color,
+        );
+        self.char_field = CharField::new(w, h);
+        self.seedling_field = CellField::new(w, h);
+        self.cooldown = vec![0; w * h];
+        self.cooldown_max = 0;
+        self.age_field = vec![0; w * h];
+        self.species_field = vec![NO_SPECIES; w * h];
+        #[cfg(feature = "multistate")]
+        {
+            self.stages = stages_from(&self.cellfield);
+        }
+        self.apply_barriers();
+    }
+}
+
+const MAX_SNAPSHOTS: usize = 9999;
+
+// saves the state every snapshot_interval steps, stops after MAX_SNAPSHOTS files
+struct AutoSnapshot {
+    written: usize,
+}
+
+impl AutoSnapshot {
+    fn new() -> AutoSnapshot {
+        AutoSnapshot { written: 0 }
+    }
+    fn update(&mut self, sim: &SimState) {
+        let interval = sim.config.snapshot_interval.floor() as usize;
+        if interval == 0 || self.written >= MAX_SNAPSHOTS || !sim.frno.is_multiple_of(interval) {
+            return;
+        }
+        state::write_snapshot(
+            &sim.config.snapshot_dir,
+            sim.frno,
+            &sim.snapshot().to_json(),
+        );
+        self.written += 1;
+        if self.written == MAX_SNAPSHOTS {
+            warn!("wrote {} snapshots, no more are saved", MAX_SNAPSHOTS);
+        }
+    }
+}
+
+// a simulation with its own texture, --compare runs two of them
+struct SimInstance {
+    sim: SimState,
+    texture: Texture2D,
+}
+
+impl SimInstance {
+    fn new(sim: SimState) -> SimInstance {
+        let texture = Texture2D::from_image(&sim.image);
+        texture.set_filter(FilterMode::Nearest);
+        SimInstance { sim, texture }
+    }
+    fn resize(&mut self, w: usize, h: usize) {
+        self.sim.resize(w, h);
+        self.texture.delete();
+        self.texture = Texture2D::from_image(&self.sim.image);
+        self.texture.set_filter(FilterMode::Nearest);
+    }
+    // uploads what changed since the last frame and draws it scaled to dest,
+    // a hexagonal grid a row at a time with the odd rows half a cell right
+    fn draw(&mut self, dest: Rect) {
+        self.sim.stats.dirty_pixels_uploaded +=
+            upload_dirty(self.texture, &self.sim.image, &self.sim.dirty) as u64;
+        match self.sim.config.grid_type {
+            GridType::Square => draw_texture_ex(
+                self.texture,
+                dest.x,
+                dest.y,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(dest.size()),
+                    ..Default::default()
+                },
+            ),
+            GridType::Hexagonal => {
+                let (w, h) = (self.sim.image.width(), self.sim.image.height());
+                let cell = vec2(dest.w / w as f32, dest.h / h as f32);
+                for y in 0..h {
+                    let shift = 0.5 * (y % 2) as f32 * cell.x;
+                    draw_texture_ex(
+                        self.texture,
+                        dest.x + shift,
+                        dest.y + y as f32 * cell.y,
+                        WHITE,
+                        DrawTextureParams {
+                            dest_size: Some(vec2(dest.w, cell.y)),
+                            source: Some(Rect::new(0., y as f32, w as f32, 1.)),
+                            ..Default::default()
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+// what fire spread depends on from the config
+struct PropagateConfig {
+    eightconn: bool,
+    // fires at least this old spread to diagonal neighbors even without eightconn
+    age_threshold: Option<usize>,
+    torus: bool,
+    hex: bool,
+    spreadprob: [f32; 8],
+    // towards HEX_NGH of even and odd rows
+    hexprob: [[f32; 6]; 2],
+    wet_resistance: f32,
+    rule: SpreadRule,
+}
+
+impl PropagateConfig {
+    fn new(config: &SimConfig) -> PropagateConfig {
+        PropagateConfig {
+            eightconn: config.eightconn,
+            age_threshold: match config.conn_by_age {
+                false => None,
+                true => Some(config.age_threshold as usize),
+            },
+            torus: config.torus,
+            hex: config.grid_type == GridType::Hexagonal,
+            spreadprob: config.spread_probs(NGH.map(|d| vec2(d[0] as f32, d[1] as f32))),
+            // a row above or below sits half a cell to the other side
+            hexprob: [0., 1.].map(|p| {
+                config.spread_probs(HEX_NGH[p as usize].map(|d| {
+                    let shift = match d[1] {
+                        0 => 0.,
+                        _ => 0.5 - p,
+                    };
+                    vec2(d[0] as f32 + shift, d[1] as f32)
+                }))
+            }),
+            wet_resistance: config.wet_resistance,
+            rule: config.spread_rule,
+        }
+    }
+}
+
+// the fields besides the trees that make a cell harder to ignite
+struct SpreadFields<'a> {
+    wet_field: &'a CellField,
+    #[cfg(not(feature = "multistate"))]
+    seedling_field: &'a CellField,
+    #[cfg(feature = "multistate")]
+    stages: &'a PackedField<2>,
+    species_field: &'a [u8],
+    cooldown: &'a [u16],
+}
+
+// the trees the fires ignite, each once, the caller clears them from cf. It only
+// reads the fields and each fire draws from its own generator seeded from
+// stepseed, so it can run in parallel with the same result
+fn propagate_fires(
+    fires: &[Fire],
+    cf: &CellField,
+    fields: &SpreadFields,
+    pcfg: &PropagateConfig,
+    stepseed: u64,
+) -> Vec<Fire> {
+    let (w, h) = (cf.w, cf.h);
+    // the rules counting burning neighbors look them up here
+    let onfire = match pcfg.rule {
+        SpreadRule::Majority(_) | SpreadRule::Threshold(_) => {
+            let mut onfire = CellField::new(w, h);
+            for f in fires {
+                onfire.set(f.x, f.y);
+            }
+            Some(onfire)
+        }
+        _ => None,
+    };
+    let onfire = onfire.as_ref();
+    let ignite = |f: &Fire| {
+        let (x, y, intensity) = (f.x, f.y, f.intensity);
+        let rng = rand::RandGenerator::new();
+        rng.srand(stepseed ^ ((y * w + x) as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        let eight = pcfg.eightconn || pcfg.age_threshold.is_some_and(|a| f.age >= a);
+        let numngh = match (pcfg.hex, eight) {
+            (true, _) => 6,
+            (false, false) => 4,
+            (false, true) => 8,
+        };
+        let nearby = match (pcfg.hex, pcfg.torus) {
+            (true, torus) => hex_count(cf, x, y, torus),
+            (false, false) => cf.neighbor_count(x, y, eight),
+            (false, true) => cf.neighbor_count_wrapping(x, y, eight),
+        };
+        // skip the neighbor loop when there is nothing to ignite
+        let reach = match nearby {
+            0 => 0,
+            _ => numngh,
+        };
+        let (dirs, probs): (&[[i32; 2]], &[f32]) = match pcfg.hex {
+            false => (&NGH, &pcfg.spreadprob),
+            true => (&HEX_NGH[y % 2], &pcfg.hexprob[y % 2]),
+        };
+        dirs.iter()
+            .zip(probs.iter())
+            .take(reach)
+            .filter_map(move |(d, p)| {
+                // weak fires only spread with probability intensity
+                let p = match intensity < 0.1 {
+                    true => *p * intensity,
+                    false => *p,
+                };
+                let p = match pcfg.rule {
+                    SpreadRule::Deterministic => 1.0,
+                    SpreadRule::Independent(q) => p * q,
+                    _ => p,
+                };
+                if p < 1.0 && rng.gen_range(0.0f32, 1.0) >= p {
+                    return None;
+                }
+                let mut nx = x as i32 + d[0];
+                let mut ny = y as i32 + d[1];
+                if pcfg.torus {
+                    nx = nx.rem_euclid(w as i32);
+                    ny = ny.rem_euclid(h as i32);
+                }
+                if nx < 0 || nx >= w as i32 || ny < 0 || ny >= h as i32 {
+                    return None;
+                }
+                let (cx, cy) = (nx as usize, ny as usize);
+                if !cf.get(cx, cy) || fields.cooldown[cy * w + cx] > 0 {
+                    return None;
+                }
+                if let Some(onfire) = onfire {
+                    let k = match (pcfg.hex, pcfg.torus) {
+                        (true, torus) => hex_count(onfire, cx, cy, torus),
+                        (false, false) => onfire.neighbor_count(cx, cy, eight),
+                        (false, true) => onfire.neighbor_count_wrapping(cx, cy, eight),
+                    };
+                    let enough = match pcfg.rule {
+                        SpreadRule::Majority(kmin) => k >= kmin,
+                        SpreadRule::Threshold(t) => k as f32 / numngh as f32 >= t,
+                        _ => true,
+                    };
+                    if !enough {
+                        return None;
+                    }
+                }
+                if fields.wet_field.get(cx, cy) && rng.gen_range(0.0f32, 1.0) <= pcfg.wet_resistance
+                {
+                    return None;
+                }
+                #[cfg(not(feature = "multistate"))]
+                let seedling = fields.seedling_field.get(cx, cy);
+                #[cfg(feature = "multistate")]
+                let seedling = fields.stages.get(cx, cy) == SEEDLING;
+                if seedling && rng.gen_range(0.0f32, 1.0) >= SEEDLING_IGNITION {
+                    return None;
+                }
+                let s = fields.species_field[cy * w + cx] as usize;
+                if s < SPECIES.len() && rng.gen_range(0.0f32, 1.0) >= SPECIES[s].ignition_prob {
+                    return None;
+                }
+                Some((cx, cy, intensity * 0.9))
+            })
+    };
+    #[cfg(not(feature = "parallel"))]
+    let ignited: Vec<(usize, usize, f32)> = fires.iter().flat_map(ignite).collect();
+    #[cfg(feature = "parallel")]
+    let ignited: Vec<(usize, usize, f32)> = fires.par_iter().flat_map_iter(ignite).collect();
+    let mut taken = CellField::new(w, h);
+    let mut spread = Vec::with_capacity(ignited.len());
+    for (cx, cy, intensity) in ignited {
+        // two fires can pick the same tree, the first one gets it
+        if taken.get(cx, cy) {
+            continue;
+        }
+        taken.set(cx, cy);
+        spread.push(Fire::new(cx, cy, intensity));
+    }
+    spread
+}
+
+// hooks for the benches into the fire spread alone
+#[cfg(feature = "bench")]
+impl SimState {
+    // sets fire to n trees spread evenly over the grid
+    pub fn light_fires(&mut self, n: usize) {
+        let trees: Vec<(usize, usize)> = self.cellfield.iter_set().collect();
+        let every = (trees.len() / n.max(1)).max(1);
+        for &(x, y) in trees.iter().step_by(every).take(n) {
+            self.cellfield.clr(x, y);
+            self.fires.front_mut().push(Fire::new(x, y, 1.0));
+        }
+    }
+    // how many trees the current fires would ignite, nothing is changed
+    pub fn propagate(&self, stepseed: u64) -> usize {
+        let fields = SpreadFields {
+            wet_field: &self.wet_field,
+            #[cfg(not(feature = "multistate"))]
+            seedling_field: &self.seedling_field,
+            #[cfg(feature = "multistate")]
+            stages: &self.stages,
+            species_field: &self.species_field,
+            cooldown: &self.cooldown,
+        };
+        let pcfg = PropagateConfig::new(&self.config);
+        propagate_fires(
+            self.fires.read_front(),
+            &self.cellfield,
+            &fields,
+            &pcfg,
+            stepseed,
+        )
+        .len()
+    }
+}
+
+impl SimState {
+    // one simulation step: fires spread and burn out, cells get wet, dry and regrow
+    pub fn step(&mut self) {
+        let SimState {
+            config,
+            cellfield,
+            wet_field,
+            char_field,
+            seedling_field,
+            cooldown,
+            cooldown_max,
+            age_field,
+            species_field,
+            burn_count,
+            burns_since: _,
+            event_log,
+            fire_field,
+            road_field,
+            water_field,
+            density_band,
+            #[cfg(feature = "multistate")]
+            stages,
+            rained,
+            doused,
+            last_rain,
+            drought,
+            fires,
+            image,
+            dirty,
+            stats,
+            frno,
+            colorphase,
+            fireproc,
+            treeproc,
+            dryproc,
+            alive_color,
+        } = self;
+        let w = image.width();
+        let h = image.height();
+        // the back buffer keeps its allocation between steps, reserve for growing
+        // fronts
+        fires.reserve_back(fires.read_front().len() * 5);
+        fire_field.arr.fill(0);
+        let emberprob = 10f32.powf(config.logemberprob);
+        let mut embers: usize = 0;
+        let mut burned: usize = 0;
+        let mut extinguished: usize = 0;
+
+        // rain puts out part of the fires and soaks the ground under them
+        if rand::gen_range(0.0f32, 1.0) < 10f32.powf(config.lograinprob) {
+            fires.front_mut().retain(|&Fire { x, y, .. }| {
+                if rand::gen_range(0.0f32, 1.0) > config.rain_intensity {
+                    return true;
+                }
+                let c = match cellfield.get(x, y) {
+                    true => *alive_color,
+                    false => BLACK,
+                };
+                set_pixel_dirty(image, dirty, x, y, c);
+                #[cfg(feature = "multistate")]
+                if !cellfield.get(x, y) {
+                    stages.set(x, y, EMPTY);
+                }
+                wet_field.set(x, y);
+                rained.push_back((x, y, *frno));
+                false
+            });
+            stats.rain_events += 1;
+            log_event(event_log, *frno, SimEvent::RainEvent);
+            *last_rain = Some(*frno);
+        }
+
+        // droughts start at random and make lightning likelier while they last
+        match drought.active {
+            false => {
+                if rand::gen_range(0.0f32, 1.0) < 10f32.powf(config.logdroughtprob) {
+                    drought.active = true;
+                    drought.remaining = (config.drought_duration.floor() as usize).max(1);
+                    log_event(event_log, *frno, SimEvent::DroughtStart);
+                }
+            }
+            true => {
+                drought.remaining -= 1;
+                if drought.remaining == 0 {
+                    drought.active = false;
+                    log_event(event_log, *frno, SimEvent::DroughtEnd);
+                }
+            }
+        }
+        if drought.active {
+            stats.drought_steps += 1;
+        }
+        stats.drought = drought.active;
+        stats.drought_frac = stats.drought_steps as f32 / (*frno + 1) as f32;
+
+        // burned out cells cool down, repainted once they are done
+        if *cooldown_max > 0 {
+            *cooldown_max -= 1;
+            for (i, c) in cooldown.iter_mut().enumerate() {
+                if *c == 0 {
+                    continue;
+                }
+                *c -= 1;
+                let (x, y) = (i % w, i / w);
+                if *c == 0 && !cellfield.get(x, y) {
+                    let c = match char_field.get(x, y) != 0 {
+                        false => BLACK,
+                        true => CHARRED_COLOR,
+                    };
+                    set_pixel_dirty(image, dirty, x, y, c);
+                }
+            }
+        }
+
+        let current = fires.share_front();
+        let pcfg = PropagateConfig::new(config);
+        let fields = SpreadFields {
+            wet_field,
+            #[cfg(not(feature = "multistate"))]
+            seedling_field,
+            #[cfg(feature = "multistate")]
+            stages,
+            species_field,
+            cooldown,
+        };
+        for f in propagate_fires(&current, cellfield, &fields, &pcfg, rand::rand() as u64) {
+            cellfield.clr(f.x, f.y);
+            age_field[f.y * w + f.x] = 0;
+            push_fire(fires, fire_field, f);
+            burned += 1;
+        }
+
+        // age out old fires
+        for &Fire {
+            x,
+            y,
+            age,
+            intensity,
+        } in current.iter()
+        {
+            // some fires die early, which leaves patchy burns
+            let early = config.extinction_prob > 0.
+                && rand::gen_range(0.0f32, 1.0) < config.extinction_prob;
+            extinguished += usize::from(early);
+            // past firemaxage fires die off over the tail, never before
+            let old = match config.death_prob(age) {
+                p if p <= 0. => false,
+                p if p >= 1. => true,
+                p => rand::gen_range(0.0f32, 1.0) < p,
+            };
+            if !early && !old {
+                let aged = Fire {
+                    x,
+                    y,
+                    age: age + 1,
+                    intensity: (intensity - config.intensity_decay).max(0.),
+                };
+                push_fire(fires, fire_field, aged);
+            } else {
+                let n = config.cooldown_frames.floor() as u16;
+                cooldown[y * w + x] = n;
+                *cooldown_max = (*cooldown_max).max(n);
+                let c = match n > 0 {
+                    false => CHARRED_COLOR,
+                    true => COOLDOWN_COLOR,
+                };
+                set_pixel_dirty(image, dirty, x, y, c);
+                char_field.mark(x, y, *frno);
+                burn_count[y * w + x] += 1;
+                seedling_field.clr(x, y);
+                #[cfg(feature = "multistate")]
+                stages.set(x, y, CHARRED);
+            }
+
+            // embers carry the fire further, capped per frame
+            if embers < MAX_EMBERS && rand::gen_range(0.0f32, 1.0) < emberprob {
+                embers += 1;
+                let (gx, gy) = gaussian_pair();
+                let ex = x as f32 + gx * config.ember_distance;
+                let ey = y as f32 + gy * config.ember_distance;
+                if ex >= 0. && ex < w as f32 && ey >= 0. && ey < h as f32 {
+                    let (cx, cy) = (ex as usize, ey as usize);
+                    if cellfield.get(cx, cy) && cooldown[cy * w + cx] == 0 {
+                        push_fire(fires, fire_field, Fire::new(cx, cy, 0.5));
+                        cellfield.clr(cx, cy);
+                        age_field[cy * w + cx] = 0;
+                        burned += 1;
+                    }
+                }
+            }
+        }
+
+        // growth follows the seasons, lightning optionally the opposite cycle
+        let season = config.season(*frno);
+        let firescale = match config.season_fire {
+            false => 1.,
+            true => 1. - season,
+        };
+
+        // spontaneous fires
+        let logfireprob = match drought.active {
+            false => config.logfireprob,
+            true => config.logfireprob + config.drought_multiplier,
+        };
+        let mut flashed = Vec::new();
+        for (x, y) in fireproc.draw(10f32.powf(logfireprob) * firescale) {
+            if fire_field.get(x, y) || !is_burnable(x, y, water_field, road_field) {
+                continue;
+            }
+            log_event(event_log, *frno, SimEvent::FireIgnited(x, y, current.len()));
+            if config.spread_mode == SpreadMode::StepByStep {
+                push_fire(fires, fire_field, Fire::new(x, y, 1.0));
+                continue;
+            }
+            // the lightning itself is not counted as burned, the trees it reaches are
+            burned += flash_fire(x, y, cellfield, &mut flashed) - 1;
+            for f in flashed.drain(..) {
+                age_field[f.y * w + f.x] = 0;
+                push_fire(fires, fire_field, f);
+            }
+        }
+
+        // new trees
+        *colorphase += config.colorspeed * std::f32::consts::TAU / 10000.;
+        let treescale = 1. + season;
+        for _ in 0..treeproc.draw(10f32.powf(config.logtreeprob) * treescale * h as f32 * w as f32)
+        {
+            let x = rand_range_usize(0, w);
+            let y = rand_range_usize(0, h);
+            if !is_burnable(x, y, water_field, road_field) {
+                continue;
+            }
+            if !cellfield.get(x, y) && rand::gen_range(0.0f32, 1.0) < 10f32.powf(config.logwetness)
+            {
+                wet_field.set(x, y);
+            }
+            // burned ground regrows on its own schedule
+            #[cfg(not(feature = "multistate"))]
+            {
+                if char_field.get(x, y) != 0 {
+                    continue;
+                }
+                if !cellfield.get(x, y) {
+                    species_field[y * w + x] = pick_species();
+                    let c = species_color(species_field[y * w + x], *colorphase);
+                    set_pixel_dirty(image, dirty, x, y, c);
+                }
+            }
+            // trees grow in two stages, nothing grows on charred ground
+            #[cfg(feature = "multistate")]
+            match stages.get(x, y) {
+                EMPTY => {
+                    species_field[y * w + x] = pick_species();
+                    stages.set(x, y, SEEDLING);
+                    set_pixel_dirty(image, dirty, x, y, SEEDLING_COLOR);
+                }
+                SEEDLING => {
+                    stages.set(x, y, MATURE);
+                    let c = species_color(species_field[y * w + x], *colorphase);
+                    set_pixel_dirty(image, dirty, x, y, c);
+                }
+                MATURE => {}
+                _ => continue,
+            }
+            cellfield.set(x, y);
+        }
+
+        // wet cells dry out
+        for _ in 0..dryproc.draw(config.dry_rate * h as f32 * w as f32) {
+            wet_field.clr(rand_range_usize(0, w), rand_range_usize(0, h));
+        }
+
+        // rained out cells dry after rain_wet_duration
+        while let Some(&(x, y, f)) = rained.front() {
+            if *frno - f < config.rain_wet_duration.floor() as usize {
+                break;
+            }
+            rained.pop_front();
+            wet_field.clr(x, y);
+        }
+
+        // tanker drops dry at the end of their TANKER_WET_STEPS-th step
+        while let Some(&(x, y, f)) = doused.front() {
+            if *frno + 1 < f {
+                break;
+            }
+            doused.pop_front();
+            wet_field.clr(x, y);
+        }
+
+        // charred cells sprout seedlings after char_duration, which mature after
+        // seedling_duration more
+        let sprout = config.char_duration.floor() as usize;
+        let mature = sprout + config.seedling_duration.floor() as usize;
+        let mut ncharred: usize = 0;
+        let mut nseedlings: usize = 0;
+        for i in 0..char_field.arr.len() {
+            let f = char_field.arr[i] as usize;
+            if f == 0 {
+                continue;
+            }
+            let (x, y) = (i % w, i / w);
+            let age = *frno + 1 - f;
+            if age < sprout {
+                ncharred += 1;
+                continue;
+            }
+            let grown = age >= mature;
+            if grown {
+                char_field.arr[i] = 0;
+            } else {
+                nseedlings += 1;
+            }
+            // a seedling that burned is cleared from cellfield until it burns out
+            match (grown, seedling_field.get(x, y), cellfield.get(x, y)) {
+                (false, false, false) => {
+                    seedling_field.set(x, y);
+                    cellfield.set(x, y);
+                    set_pixel_dirty(image, dirty, x, y, SEEDLING_COLOR);
+                    #[cfg(feature = "multistate")]
+                    stages.set(x, y, SEEDLING);
+                }
+                (true, true, tree) => {
+                    seedling_field.clr(x, y);
+                    if tree {
+                        let c = species_color(species_field[y * w + x], *colorphase);
+                        set_pixel_dirty(image, dirty, x, y, c);
+                        #[cfg(feature = "multistate")]
+                        stages.set(x, y, MATURE);
+                    }
+                }
+                (true, false, false) => {
+                    cellfield.set(x, y);
+                    species_field[y * w + x] = pick_species();
+                    set_pixel_dirty(
+                        image,
+                        dirty,
+                        x,
+                        y,
+                        species_color(species_field[y * w + x], *colorphase),
+                    );
+                    #[cfg(feature = "multistate")]
+                    stages.set(x, y, MATURE);
+                }
+                _ => {}
+            }
+        }
+
+        // trees age, cells cleared by brushes or fires start over; trees planted
+        // outside of growth, by brushes or at startup, get their species here
+        for (x, y) in cellfield.iter_clr() {
+            age_field[y * w + x] = 0;
+            species_field[y * w + x] = NO_SPECIES;
+        }
+        for (x, y) in cellfield.iter_set() {
+            if species_field[y * w + x] == NO_SPECIES {
+                species_field[y * w + x] = pick_species();
+                if !config.age_color && !seedling_field.get(x, y) {
+                    let c = species_color(species_field[y * w + x], *colorphase);
+                    set_pixel_dirty(image, dirty, x, y, c);
+                }
+            }
+            let age = age_field[y * w + x].saturating_add(1);
+            age_field[y * w + x] = age;
+            // repaint only every 64 steps, the color changes slowly
+            if config.age_color && age % 64 == 1 && !seedling_field.get(x, y) {
+                set_pixel_dirty(image, dirty, x, y, tree_age_color(age));
+            }
+        }
+
+        // current has to go first, or the swap can not reuse its buffer
+        let last = current.last().map(|f| (f.x, f.y));
+        drop(current);
+        fires.swap();
+        if let (true, Some((x, y))) = (fires.read_front().is_empty(), last) {
+            log_event(event_log, *frno, SimEvent::FireExtinguished(x, y));
+        }
+
+        for f in fires.read_front() {
+            let c = match config.perimeter_mode {
+                false => fire_color(f, config),
+                true => match is_perimeter_fire(f.x, f.y, fire_field, config.eightconn) {
+                    false => WHITE,
+                    true => ORANGE,
+                },
+            };
+            set_pixel_dirty(image, dirty, f.x, f.y, c);
+        }
+
+        if false {
+            fires.front_mut().sort_by(|a, b| {
+                cellfield
+                    .indices(b.x, b.y)
+                    .0
+                    .cmp(&cellfield.indices(a.x, a.y).0)
+            });
+        }
+
+        stats.update(cellfield, fires.read_front(), burned);
+        stats.extinguished_last = extinguished;
+        let band = percolation_status(stats.density, config.eightconn, config.grid_type).1;
+        if !density_band.is_empty() && band != *density_band {
+            log_event(
+                event_log,
+                *frno,
+                SimEvent::DensityAlert(stats.density as f32),
+            );
+        }
+        *density_band = band;
+        stats.update_stages(ncharred, nseedlings);
+        stats.season = treescale;
+        if frno.is_multiple_of(10) {
+            stats.update_clusters(fires.read_front(), w);
+            stats.update_ages(cellfield, age_field);
+            stats.update_burns(burn_count);
+        }
+        // the whole grid is labeled, so less often
+        if frno.is_multiple_of(60) {
+            stats.update_forest(cellfield);
+        }
+        *frno += 1;
+    }
+}
+
+// the controls saved by the last window win over forestfire.toml when prefs is
+// set, headless runs leave them out to stay reproducible
+fn initial_config(cli: &CliArgs, prefs: bool) -> SimConfig {
+    let saved = match prefs {
+        false => None,
+        true => state::load_prefs(),
+    };
+    let built = match saved.or_else(state::read_config) {
+        Some(config) => SimConfigBuilder::from_config(config).with_cli(cli).build(),
+        None => SimConfigBuilder::from(cli).build(),
+    };
+    match built {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("invalid settings: {}", e);
+            exit(2);
+        }
+    }
+}
+
+// --import-png, --ignition-map and --ignite-clusters, a file that can not be read ends the program
+#[cfg(not(target_arch = "wasm32"))]
+fn load_start_or_exit(sim: &mut SimState, cli: &CliArgs) {
+    if let Some(path) = &cli.mmap_state {
+        match CellField::new_mmap(sim.cellfield.w, sim.cellfield.h, path) {
+            Ok(cf) => sim.map_trees(cf),
+            Err(e) => {
+                eprintln!("could not map {}: {}", path, e);
+                exit(2);
+            }
+        }
+    }
+    if let Some(path) = &cli.import_png {
+        match state::load_initial_from_png(path, sim.cellfield.w, sim.cellfield.h) {
+            Ok((cf, fires)) => sim.import(cf, fires),
+            Err(e) => {
+                eprintln!("could not import {}: {}", path, e);
+                exit(2);
+            }
+        }
+    }
+    if let Some(path) = &cli.ignition_map {
+        let (w, h) = (sim.cellfield.w, sim.cellfield.h);
+        match state::load_rate_field_from_png(path, w, h) {
+            Ok(rates) => sim.fireproc = PoissonProcess2D::from_rates(rates, w, h),
+            Err(e) => {
+                eprintln!("could not import {}: {}", path, e);
+                exit(2);
+            }
+        }
+    }
+    if let Some(path) = &cli.water_png {
+        match state::load_water_from_png(path, sim.cellfield.w, sim.cellfield.h) {
+            Ok(water) => {
+                sim.water_field = water;
+                sim.apply_barriers();
+            }
+            Err(e) => {
+                eprintln!("could not import {}: {}", path, e);
+                exit(2);
+            }
+        }
+    }
+    if let Some(path) = &cli.roads {
+        match state::read_roads(path) {
+            Ok(segs) => {
+                rasterize_roads(&segs, cli.road_width, &mut sim.road_field, &mut sim.image);
+                sim.apply_barriers();
+            }
+            Err(e) => {
+                eprintln!("could not read {}: {}", path, e);
+                exit(2);
+            }
+        }
+    }
+    if let Some(path) = &cli.ignite_clusters {
+        match state::read_clusters(path) {
+            Ok(clusters) => sim.ignite_clusters(&clusters),
+            Err(e) => {
+                eprintln!("could not read {}: {}", path, e);
+                exit(2);
+            }
+        }
+    }
+}
+
+// frames the controls have to stay put before they are saved
+const PREFS_DELAY: usize = 180;
+
+// writes the config for the next session once it stops changing, the seed stays
+// the one the session started with
+struct PrefsSaver {
+    seed: Option<u64>,
+    seen: String,
+    saved: String,
+    still: usize,
+}
+
+impl PrefsSaver {
+    fn new(config: &SimConfig) -> PrefsSaver {
+        let json = serde_json::to_string(config).unwrap_or_default();
+        PrefsSaver {
+            seed: config.seed,
+            seen: json.clone(),
+            saved: json,
+            still: 0,
+        }
+    }
+    fn update(&mut self, config: &SimConfig) {
+        let config = SimConfig {
+            seed: self.seed,
+            ..config.clone()
+        };
+        let Ok(json) = serde_json::to_string(&config) else {
+            return;
+        };
+        match json == self.seen {
+            false => {
+                self.seen = json;
+                self.still = 0;
+            }
+            true => self.still += 1,
+        }
+        if self.still == PREFS_DELAY && self.seen != self.saved {
+            state::save_prefs(&config);
+            self.saved = self.seen.clone();
+        }
+    }
+}
+
+// simulated time per tick, and the most ticks run in one rendered frame
+const STEP_DT: f32 = 1. / 60.;
+const MAX_SUBSTEPS: usize = 4;
+
+// adds a frame of frame_time to the time not yet simulated and takes the whole
+// ticks out of it, at most MAX_SUBSTEPS
+fn take_ticks(dt_accum: &mut f32, frame_time: f32) -> usize {
+    let mut nticks = 0;
+    *dt_accum += frame_time;
+    while *dt_accum >= STEP_DT && nticks < MAX_SUBSTEPS {
+        *dt_accum -= STEP_DT;
+        nticks += 1;
+    }
+    // too far behind to catch up, drop the rest
+    if nticks == MAX_SUBSTEPS {
+        *dt_accum = 0.;
+    }
+    nticks
+}
+
+// the .npy file from --export-numpy, for the grid the simulation starts with
+fn open_npy(cli: &CliArgs, sim: &SimState) -> Option<NpyWriter> {
+    let path = cli.export_numpy.as_deref()?;
+    NpyWriter::new(path, sim.cellfield.w, sim.cellfield.h)
+        .map_err(|e| warn!("could not write {}: {}", path, e))
+        .ok()
+}
+
+// stops writing at the first error, a resized window is one
+fn record_npy(npy: &mut Option<NpyWriter>, sim: &SimState) {
+    let frame = npy
+        .as_mut()
+        .map(|n| n.append_frame(&sim.cellfield, &sim.char_field, sim.fires.read_front()));
+    if let Some(Err(e)) = frame {
+        warn!("stopped the numpy export: {}", e);
+        finish_npy(npy);
+    }
+}
+
+fn finish_npy(npy: &mut Option<NpyWriter>) {
+    if let Some(Err(e)) = npy.take().map(|n| n.finalize()) {
+        warn!("could not write the numpy export: {}", e);
+    }
+}
+
+// the same steps a window would run with the same seed, timed, with nothing drawn
+fn run_headless(cli: &CliArgs, n: usize) {
+    let config = initial_config(cli, false);
+    let w = config.window_width as usize;
+    let h = config.window_height as usize;
+    if let Some(seed) = config.seed {
+        rand::srand(seed);
+    }
+    let mut sim = SimState::new(config, w, h);
+    #[cfg(not(target_arch = "wasm32"))]
+    load_start_or_exit(&mut sim, cli);
+    let mut stats_csv = StatsRecorder::new(cli.stats_csv.as_deref(), cli.stats_interval);
+    let mut npy = open_npy(cli, &sim);
+    let mut autosnap = AutoSnapshot::new();
+    let start = std::time::Instant::now();
+    for _ in 0..n {
+        sim.step();
+        stats_csv.record(sim.frno, &sim.stats);
+        record_npy(&mut npy, &sim);
+        autosnap.update(&sim);
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    stats_csv.finish();
+    finish_npy(&mut npy);
+    println!(
+        "{} frames, {:.3}s, {:.1} fps, final tree_count={}, fire_count={}",
+        n,
+        elapsed,
+        n as f64 / elapsed,
+        sim.cellfield.count_set(),
+        sim.fires.read_front().len()
+    );
+}
+
+// the first line of the stats, miniquad 0.3 only takes a window title as the
+// window opens so it can not go there
+fn format_window_title(stats: &SimStats, frno: usize) -> String {
+    format!(
+        "Forest Fires | Trees: {:.1}% | Fires: {} | Frame: {}",
+        stats.density * 100.,
+        stats.fire_count,
+        frno
+    )
+}
+
+// the window config, main.rs hands it to macroquad
+pub fn conf() -> Conf {
+    let cli = parse_cli();
+    // conf() runs before the window opens, a headless run never opens one
+    if let Some(n) = cli.headless {
+        run_headless(&cli, n);
+        exit(0);
+    }
+    let config = initial_config(&cli, true);
+    Conf {
+        window_title: String::from("Forest Fires: <space> or double touch for controls"),
+        window_width: config.window_width,
+        window_height: config.window_height,
+        high_dpi: false,
+        ..Default::default()
+    }
+}
+
+// ctrl on either side
+fn ctrl_down() -> bool {
+    is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl)
+}
+
+// what the buttons in the controls asked for this frame
+#[derive(Default)]
+struct Actions {
+    rain: bool,
+    reseed: bool,
+    save_config: bool,
+    toggle_gif: bool,
+    save_state: bool,
+    load_state: bool,
+    save_fires: bool,
+    load_fires: bool,
+    export_heatmap: bool,
+    export_fri: bool,
+    save_svg: bool,
+    toggle_replay: bool,
+    toggle_playback: bool,
+    save_events: bool,
+}
+
+// what is left to do in a frame once its input is handled
+#[derive(Default)]
+struct Frame {
+    step_key: bool,
+    screenshot: bool,
+    reseed: bool,
+    resize: Option<(usize, usize)>,
+    // a png and a gif frame are saved when a step they were due at ran
+    record: bool,
+    gif_frame: bool,
+}
+
+// what the window keeps around its simulations, the controls, the tools, the
+// view and the recorders
+struct App {
+    no_ui: bool,
+    compare: bool,
+    // the instance keys, the mouse and the controls act on
+    active: usize,
+    two_finger_tap: Rc<Cell<bool>>,
+    showpopup: DebounceToggle<Box<dyn Fn() -> bool>>,
+    showstats: DebounceToggle<fn() -> bool>,
+    popup_open: bool,
+    species_legend: bool,
+    help_visible: bool,
+    tutorial: TutorialState,
+    toast: Option<Toast>,
+    prefs: PrefsSaver,
+    stats_csv: StatsRecorder,
+    npy: Option<NpyWriter>,
+    crackle: FireSound,
+    autosnap: AutoSnapshot,
+    heatmap: bool,
+    fri_overlay: bool,
+    heat: Option<(Image, Texture2D)>,
+    density_overlay: bool,
+    density_radius: f32,
+    // the overlay, and the map it and the zoomed in tooltip show with the step
+    // and radius it was made for
+    dens: Option<(Image, Texture2D)>,
+    density_map: Option<(Vec<f32>, usize, usize)>,
+    minimap_enabled: bool,
+    minimap: MiniMap,
+    replay: ReplayMode,
+    // live fires and the image without them while a recording plays back
+    playback: Option<(Vec<Fire>, Image)>,
+    recording: bool,
+    rfrm: usize,
+    recskip: f32,
+    gif: Option<GifRecorder>,
+    pngs: PngSaver,
+    brush: BrushTool,
+    brush_radius: f32,
+    prev_mouse: Option<(usize, usize)>,
+    right_plant: bool,
+    rect: RectTool,
+    undo: UndoStack,
+    // the cells as they were when the current tree or erase stroke started
+    stroke_before: Option<CellField>,
+    camera: Camera,
+    // what the frame is drawn with, the camera of the active half when comparing,
+    // and the cell under the mouse in it
+    view: Camera,
+    cursor: Option<(usize, usize)>,
+    prev_screen_mouse: Vec2,
+    prev_touch_positions: Option<[Vec2; 2]>,
+    two_touch_start: Option<(f64, [Vec2; 2])>,
+    paused: bool,
+    steps_per_frame: f32,
+    // wall clock time not yet simulated, and the ticks of STEP_DT run so far
+    dt_accum: f32,
+    ticks: usize,
+    target_fps: f32,
+    drops_remaining: usize,
+    drop_radius: f32,
+    frame_times: FrameTimes,
+    texture_stale: bool,
+}
+
+impl App {
+    async fn new(cli: &CliArgs, sim: &SimState) -> App {
+        let two_finger_tap = Rc::new(Cell::new(false));
+        let tap = Rc::clone(&two_finger_tap);
+        let radius = 3;
+        App {
+            no_ui: cli.no_ui,
+            compare: cli.compare,
+            active: 0,
+            two_finger_tap,
+            showpopup: DebounceToggle::with_timing(
+                Box::new(move || is_key_down(KeyCode::Space) || tap.get()),
+                1,
+                1,
+            ),
+            showstats: DebounceToggle::new(|| is_key_down(KeyCode::S) && !ctrl_down()),
+            popup_open: false,
+            species_legend: false,
+            help_visible: false,
+            tutorial: TutorialState::new(),
+            toast: None,
+            prefs: PrefsSaver::new(&sim.config),
+            stats_csv: StatsRecorder::new(cli.stats_csv.as_deref(), cli.stats_interval),
+            npy: open_npy(cli, sim),
+            crackle: FireSound::new().await,
+            autosnap: AutoSnapshot::new(),
+            heatmap: false,
+            fri_overlay: false,
+            heat: None,
+            density_overlay: false,
+            density_radius: 5.,
+            dens: None,
+            density_map: None,
+            minimap_enabled: true,
+            minimap: MiniMap::new(),
+            replay: ReplayMode::new(),
+            playback: None,
+            recording: false,
+            rfrm: 0,
+            recskip: 1.,
+            gif: None,
+            pngs: PngSaver::new(),
+            brush: BrushTool {
+                mode: BrushMode::Fire,
+                radius,
+            },
+            brush_radius: radius as f32,
+            prev_mouse: None,
+            right_plant: false,
+            rect: RectTool {
+                anchor: None,
+                clip: None,
+            },
+            undo: UndoStack::new(),
+            stroke_before: None,
+            camera: Camera::new(),
+            view: Camera::new(),
+            cursor: None,
+            prev_screen_mouse: Vec2::from(mouse_position()),
+            prev_touch_positions: None,
+            two_touch_start: None,
+            paused: false,
+            steps_per_frame: cli.steps.unwrap_or(1.),
+            dt_accum: 0.,
+            ticks: 0,
+            target_fps: 60.,
+            drops_remaining: TANKER_DROPS,
+            drop_radius: 20.,
+            frame_times: FrameTimes::new(),
+            texture_stale: false,
+        }
+    }
+
+    // compared simulations each get half of the window
+    fn halves(&self) -> usize {
+        match self.compare {
+            false => 1,
+            true => 2,
+        }
+    }
+
+    // the overlays are made again at the new size
+    fn resized(&mut self) {
+        if let Some((_, t)) = self.heat.take() {
+            t.delete();
+        }
+        if let Some((_, t)) = self.dens.take() {
+            t.delete();
+        }
+        self.density_map = None;
+    }
+
+    // the simulation keeps to STEP_DT of wall time per tick whatever the frame
+    // rate, steps_per_frame is steps per tick and 0 runs one step every other
+    // tick, N steps once while paused. Returns the steps run, the other
+    // simulations of --compare run as many
+    fn step(&mut self, sim: &mut SimState, frame: &mut Frame) -> usize {
+        let halted = self.paused || self.playback.is_some();
+        let nticks = match halted {
+            false => take_ticks(&mut self.dt_accum, get_frame_time()),
+            true => {
+                self.dt_accum = 0.;
+                0
+            }
+        };
+        let nsteps = match (halted, self.steps_per_frame.floor() as usize) {
+            (true, _) => usize::from(frame.step_key && self.playback.is_none()),
+            (false, 0) => (self.ticks..self.ticks + nticks)
+                .filter(|t| t % 2 == 0)
+                .count(),
+            (false, n) => n * nticks,
+        };
+        self.ticks += nticks;
+
+        let recskip = self.recskip.floor() as usize;
+        for _ in 0..nsteps {
+            frame.record |= self.recording && sim.frno.is_multiple_of(recskip);
+            frame.gif_frame |= self.gif.is_some() && sim.frno.is_multiple_of(recskip);
+            sim.step();
+            self.stats_csv.record(sim.frno, &sim.stats);
+            record_npy(&mut self.npy, sim);
+            self.autosnap.update(sim);
+            if self.replay.recording {
+                self.replay.record(sim.fires.read_front());
+            }
+        }
+        let burning = match halted {
+            false => sim.stats.fire_count,
+            true => 0,
+        };
+        self.crackle
+            .update(burning, sim.image.width() * sim.image.height());
+        nsteps
+    }
+
+    // what the frame brings up to date besides the cells: the density map, the
+    // stats, the saved config and the files being recorded
+    fn update(&mut self, sim: &mut SimState, frame: &Frame) {
+        let (w, h) = (sim.image.width(), sim.image.height());
+        // the map is redone every 30 steps or when the radius changes
+        let overlay = self.density_overlay && !self.compare;
+        let mut repaint = false;
+        match overlay || self.view.zoom > 1. {
+            false => self.density_map = None,
+            true => {
+                let radius = self.density_radius as usize;
+                let stale = match &self.density_map {
+                    None => true,
+                    Some((_, frno, r)) => *r != radius || sim.frno.abs_diff(*frno) >= 30,
+                };
+                if stale {
+                    self.density_map =
+                        Some((sim.cellfield.density_map_box(radius), sim.frno, radius));
+                    repaint = true;
+                }
+            }
+        }
+        match (overlay, &self.density_map) {
+            (true, Some((map, _, _))) => {
+                if repaint || self.dens.is_none() || sim.stats.box_density.is_none() {
+                    let (img, t) = self.dens.get_or_insert_with(|| {
+                        let img = Image::gen_image_color(w as u16, h as u16, BLANK);
+                        let t = Texture2D::from_image(&img);
+                        t.set_filter(FilterMode::Nearest);
+                        (img, t)
+                    });
+                    paint_density(map, w, img);
+                    t.update(img);
+                    sim.stats.box_density = Some(mean_std(map));
+                }
+            }
+            _ => sim.stats.box_density = None,
+        }
+        sim.stats.pending_saves = self.pngs.pending();
+        self.prefs.update(&sim.config);
+        sim.stats.drops_remaining = self.drops_remaining;
+
+        if frame.record {
+            self.pngs
+                .save(&sim.image, format!("frm{:05}.png", self.rfrm));
+            self.rfrm += 1;
+        }
+        if frame.screenshot {
+            let text = match cfg!(target_arch = "wasm32") {
+                false => {
+                    let path = format!("screenshot_{:05}.png", sim.frno);
+                    self.pngs.save(&sim.image, path.clone());
+                    format!("Saved {}", path)
+                }
+                true => "Screenshots are not available on the web".to_string(),
+            };
+            self.toast = Some(Toast { text, ttl: 90 });
+        }
+        if frame.gif_frame {
+            if let Some(Err(e)) = self.gif.as_mut().map(|g| g.add_frame(&sim.image)) {
+                macroquad::logging::warn!("could not write gif frame: {}", e);
+                self.gif = None;
+            }
+        }
+    }
+}
+
+impl SimState {
+    // the keys, the controls, the mouse and the touches of a frame, acting on this
+    // simulation, the window is left for run to resize and reseed
+    fn handle_input(&mut self, app: &mut App) -> Frame {
+        let mut frame = self.handle_keys(app);
+        let age_color = self.config.age_color;
+        let actions = self.controls(app);
+        self.apply_actions(app, &actions, &mut frame);
+        if self.config.age_color != age_color {
+            self.repaint_trees();
+        }
+        self.config.wind = Vec2::from_angle(self.config.wind_angle.to_radians());
+        self.handle_pointer(app, actions.rain);
+        frame
+    }
+
+    fn handle_keys(&mut self, app: &mut App) -> Frame {
+        if is_key_down(KeyCode::Q) {
+            app.stats_csv.finish();
+            finish_npy(&mut app.npy);
+            app.pngs.finish();
+            exit(0);
+        }
+        if is_key_pressed(KeyCode::P) {
+            app.paused = !app.paused;
+        }
+        let step_key = app.paused && is_key_pressed(KeyCode::N);
+        if is_key_pressed(KeyCode::F) {
+            app.brush.mode = BrushMode::Fire;
+        }
+        if is_key_pressed(KeyCode::T) {
+            app.brush.mode = BrushMode::Tree;
+        }
+        if is_key_pressed(KeyCode::E) {
+            app.brush.mode = BrushMode::Erase;
+        }
+        if is_key_pressed(KeyCode::G) {
+            app.brush.mode = BrushMode::Flood;
+        }
+        if is_key_pressed(KeyCode::V) && !ctrl_down() {
+            app.brush.mode = BrushMode::Rect;
+        }
+        if is_key_pressed(KeyCode::W) {
+            app.brush.mode = BrushMode::Water;
+        }
+        if is_key_pressed(KeyCode::K) && app.playback.is_none() {
+            self.kill_fires();
+        }
+        if is_key_pressed(KeyCode::C) && app.playback.is_none() {
+            self.clear_trees();
+        }
+        if app.playback.is_some() {
+            let fires = self.fires.front_mut();
+            let moved = match () {
+                _ if is_key_pressed(KeyCode::Left) => app.replay.step_back(fires),
+                _ if is_key_pressed(KeyCode::Right) => app.replay.step_forward(fires),
+                _ if is_key_pressed(KeyCode::Home) => app.replay.restart(fires),
+                _ => false,
+            };
+            if let (true, Some((_, base))) = (moved, &app.playback) {
+                self.paint_fires_over(base);
+            }
+        }
+        if is_key_pressed(KeyCode::H) {
+            app.help_visible = !app.help_visible;
+        }
+        // escape closes the help and the controls
+        if is_key_pressed(KeyCode::Escape) {
+            app.help_visible = false;
+            app.showpopup.reset();
+        }
+        if is_key_pressed(KeyCode::R) {
+            self.burn_count.fill(0);
+            self.burns_since = self.frno;
+        }
+        let screenshot = ctrl_down() && is_key_pressed(KeyCode::S);
+        if ctrl_down() && app.playback.is_none() {
+            let changed = match () {
+                _ if is_key_pressed(KeyCode::Z) => undo::apply_undo(
+                    &mut app.undo,
+                    &mut self.cellfield,
+                    &mut self.image,
+                    self.alive_color,
+                ),
+                _ if is_key_pressed(KeyCode::Y) => undo::apply_redo(
+                    &mut app.undo,
+                    &mut self.cellfield,
+                    &mut self.image,
+                    self.alive_color,
+                ),
+                _ => false,
+            };
+            // an edit from before water was drawn may put trees back on it
+            if changed {
+                self.apply_barriers();
+            }
+        }
+        Frame {
+            step_key,
+            screenshot,
+            ..Default::default()
+        }
+    }
+
+    // the controls window, when it is open
+    fn controls(&mut self, app: &mut App) -> Actions {
+        let mut actions = Actions::default();
+        let popup_open = !app.no_ui && app.showpopup.get();
+        app.popup_open = popup_open;
+        if popup_open {
+            let x = 100. + (app.active * self.image.width()) as f32;
+            widgets::Window::new(
+                hash!("controls", app.active),
+                vec2(x, 100.),
+                vec2(300., 200.),
+            )
+            .label(&format!("Step {}", self.frno))
+            .ui(&mut root_ui(), |ui| {
+                for (i, (name, preset)) in PRESETS.iter().enumerate() {
+                    if i > 0 {
+                        ui.same_line(0.);
+                    }
+                    if ui.button(None, name.as_str()) {
+                        self.config.apply_preset(preset);
+                    }
+                }
+                ui.slider(
+                    hash!(),
+                    "logfireprob",
+                    -10f32..-5f32,
+                    &mut self.config.logfireprob,
+                );
+                ui.slider(
+                    hash!(),
+                    "logtreeprob",
+                    -10f32..-2f32,
+                    &mut self.config.logtreeprob,
+                );
+                ui.slider(
+                    hash!(),
+                    "colorspeed",
+                    0f32..10f32,
+                    &mut self.config.colorspeed,
+                );
+                ui.slider(
+                    hash!(),
+                    "firemaxage",
+                    1f32..20f32,
+                    &mut self.config.firemaxage,
+                );
+                ui.slider(
+                    hash!(),
+                    "tail_length",
+                    0f32..20f32,
+                    &mut self.config.tail_length,
+                );
+                let mut grid = self.config.grid_type.index();
+                ui.combo_box(hash!(), "grid", &GRID_TYPES, &mut grid);
+                self.config.grid_type = GridType::from_index(grid);
+                ui.checkbox(hash!(), "8-connected", &mut self.config.eightconn);
+                ui.checkbox(
+                    hash!(),
+                    "8-connected once fires are older",
+                    &mut self.config.conn_by_age,
+                );
+                ui.slider(
+                    hash!(),
+                    "age_threshold",
+                    0f32..20f32,
+                    &mut self.config.age_threshold,
+                );
+                ui.checkbox(hash!(), "torus", &mut self.config.torus);
+                ui.checkbox(hash!(), "color trees by age", &mut self.config.age_color);
+                ui.checkbox(
+                    hash!(),
+                    "fire front in orange, interior in white",
+                    &mut self.config.perimeter_mode,
+                );
+                ui.checkbox(hash!(), "species legend", &mut app.species_legend);
+                ui.checkbox(hash!(), "Heatmap", &mut app.heatmap);
+                ui.checkbox(hash!(), "mini-map when zoomed", &mut app.minimap_enabled);
+                if ui.button(None, "Export Heatmap") {
+                    actions.export_heatmap = true;
+                }
+                ui.checkbox(hash!(), "FRI Overlay", &mut app.fri_overlay);
+                ui.checkbox(hash!(), "Density Overlay", &mut app.density_overlay);
+                ui.slider(
+                    hash!(),
+                    "density_radius",
+                    1f32..20f32,
+                    &mut app.density_radius,
+                );
+                if ui.button(None, "Export FRI") {
+                    actions.export_fri = true;
+                }
+                let mut rule = self.config.spread_rule.index();
+                ui.combo_box(hash!(), "spread rule", &SPREAD_RULES, &mut rule);
+                if rule != self.config.spread_rule.index() {
+                    self.config.spread_rule = SpreadRule::from_index(rule);
+                }
+                match &mut self.config.spread_rule {
+                    SpreadRule::Deterministic => {}
+                    SpreadRule::Independent(p) => {
+                        ui.slider(hash!(), "spread chance", 0f32..1f32, p);
+                    }
+                    SpreadRule::Majority(k) => {
+                        let mut kf = *k as f32;
+                        ui.slider(hash!(), "burning neighbors", 1f32..8f32, &mut kf);
+                        *k = kf.round() as u8;
+                    }
+                    SpreadRule::Threshold(t) => {
+                        ui.slider(hash!(), "burning fraction", 0f32..1f32, t);
+                    }
+                }
+                let mut flash = self.config.spread_mode == SpreadMode::FlashFire;
+                ui.checkbox(hash!(), "fires burn their whole forest at once", &mut flash);
+                self.config.spread_mode = match flash {
+                    false => SpreadMode::StepByStep,
+                    true => SpreadMode::FlashFire,
+                };
+                let mut hsvfire = self.config.fire_color_mode == FireColorMode::Hsv;
+                ui.checkbox(hash!(), "HSV fire colors", &mut hsvfire);
+                self.config.fire_color_mode = match hsvfire {
+                    false => FireColorMode::Classic,
+                    true => FireColorMode::Hsv,
+                };
+                ui.slider(
+                    hash!(),
+                    "wind_speed",
+                    0f32..10f32,
+                    &mut self.config.wind_speed,
+                );
+                ui.slider(
+                    hash!(),
+                    "wind_angle",
+                    0f32..360f32,
+                    &mut self.config.wind_angle,
+                );
+                ui.slider(hash!(), "gravity", 0f32..5f32, &mut self.config.gravity);
+                ui.slider(
+                    hash!(),
+                    "logwetness",
+                    -5f32..0f32,
+                    &mut self.config.logwetness,
+                );
+                ui.slider(
+                    hash!(),
+                    "wet_resistance",
+                    0f32..1f32,
+                    &mut self.config.wet_resistance,
+                );
+                ui.slider(hash!(), "drop_radius", 5f32..60f32, &mut app.drop_radius);
+                if ui.button(None, "Refill Tanker") {
+                    app.drops_remaining = TANKER_DROPS;
+                }
+                if ui.button(None, "Rain") {
+                    actions.rain = true;
+                }
+                ui.slider(
+                    hash!(),
+                    "logemberprob",
+                    -6f32..-1f32,
+                    &mut self.config.logemberprob,
+                );
+                ui.slider(
+                    hash!(),
+                    "ember_distance",
+                    5f32..200f32,
+                    &mut self.config.ember_distance,
+                );
+                ui.slider(
+                    hash!(),
+                    "intensity_decay",
+                    0f32..0.1f32,
+                    &mut self.config.intensity_decay,
+                );
+                ui.slider(
+                    hash!(),
+                    "extinction_prob",
+                    0f32..0.3f32,
+                    &mut self.config.extinction_prob,
+                );
+                ui.slider(
+                    hash!(),
+                    "lograinprob",
+                    -6f32..-1f32,
+                    &mut self.config.lograinprob,
+                );
+                ui.slider(
+                    hash!(),
+                    "rain_intensity",
+                    0f32..1f32,
+                    &mut self.config.rain_intensity,
+                );
+                ui.slider(
+                    hash!(),
+                    "rain_wet_duration",
+                    0f32..2000f32,
+                    &mut self.config.rain_wet_duration,
+                );
+                ui.slider(
+                    hash!(),
+                    "logdroughtprob",
+                    -6f32..-2f32,
+                    &mut self.config.logdroughtprob,
+                );
+                ui.slider(
+                    hash!(),
+                    "drought_duration",
+                    100f32..5000f32,
+                    &mut self.config.drought_duration,
+                );
+                ui.slider(
+                    hash!(),
+                    "drought_multiplier",
+                    1f32..3f32,
+                    &mut self.config.drought_multiplier,
+                );
+                ui.slider(
+                    hash!(),
+                    "steps_per_frame",
+                    0f32..200f32,
+                    &mut app.steps_per_frame,
+                );
+                ui.slider(hash!(), "target_fps", 5f32..240f32, &mut app.target_fps);
+                let ptext = match app.paused {
+                    false => "Pause",
+                    true => "Resume",
+                };
+                if ui.button(None, ptext) {
+                    app.paused = !app.paused;
+                }
+                if ui.button(None, "Re-seed") {
+                    actions.reseed = true;
+                }
+                // applies from the next Re-seed
+                ui.checkbox(hash!(), "perlin start", &mut self.config.use_perlin_init);
+                ui.slider(
+                    hash!(),
+                    "noise_scale",
+                    0.001f32..0.05f32,
+                    &mut self.config.noise_scale,
+                );
+                ui.slider(
+                    hash!(),
+                    "noise_threshold",
+                    0f32..1f32,
+                    &mut self.config.noise_threshold,
+                );
+                if ui.button(None, "Save Config") {
+                    actions.save_config = true;
+                }
+                ui.slider(hash!(), "brush_radius", 1f32..30f32, &mut app.brush_radius);
+                ui.slider(
+                    hash!(),
+                    "char_duration",
+                    0f32..500f32,
+                    &mut self.config.char_duration,
+                );
+                ui.slider(
+                    hash!(),
+                    "seedling_duration",
+                    0f32..1000f32,
+                    &mut self.config.seedling_duration,
+                );
+                ui.slider(
+                    hash!(),
+                    "cooldown_frames",
+                    0f32..500f32,
+                    &mut self.config.cooldown_frames,
+                );
+                ui.slider(
+                    hash!(),
+                    "season_period",
+                    1000f32..100000f32,
+                    &mut self.config.season_period,
+                );
+                ui.slider(
+                    hash!(),
+                    "season_amplitude",
+                    0f32..1f32,
+                    &mut self.config.season_amplitude,
+                );
+                ui.checkbox(
+                    hash!(),
+                    "fewer fires in growth season",
+                    &mut self.config.season_fire,
+                );
+
+                ui.tree_node(hash!(), "Events", |ui| {
+                    widgets::Group::new(hash!(), vec2(280., 120.)).ui(ui, |ui| {
+                        for (frame, event) in self.event_log.iter().rev().take(10) {
+                            ui.label(None, &format!("{:>7} {:?}", frame, event));
+                        }
+                    });
+                    if ui.button(None, "Save Events CSV") {
+                        actions.save_events = true;
+                    }
+                });
+                ui.tree_node(hash!(), "Save PNG", |ui| {
+                    let btext: String = match app.recording {
+                        false => "Start Recording".to_string(),
+                        true => format!("Recording {}", app.rfrm).to_string(),
+                    };
+                    if ui.button(None, btext) {
+                        app.rfrm = 0;
+                        app.recording = !app.recording;
+                    }
+                    let gtext = match &app.gif {
+                        None => "Record GIF".to_string(),
+                        Some(g) => format!("Stop GIF {}", g.frame_count()),
+                    };
+                    if ui.button(None, gtext) {
+                        actions.toggle_gif = true;
+                    }
+                    ui.slider(hash!(), "recskip", 1f32..10f32, &mut app.recskip);
+                    ui.slider(
+                        hash!(),
+                        "snapshot_interval",
+                        0f32..10000f32,
+                        &mut self.config.snapshot_interval,
+                    );
+                    if ui.button(None, "Save State") {
+                        actions.save_state = true;
+                    }
+                    if ui.button(None, "Export SVG") {
+                        actions.save_svg = true;
+                    }
+                    if ui.button(None, "Load State") {
+                        actions.load_state = true;
+                    }
+                    if ui.button(None, "Save Fires") {
+                        actions.save_fires = true;
+                    }
+                    ui.same_line(0.);
+                    if ui.button(None, "Load Fires") {
+                        actions.load_fires = true;
+                    }
+                    let rtext = match app.replay.recording {
+                        false => "Record Replay".to_string(),
+                        true => format!("Stop Replay {}", app.replay.buffer.len()),
+                    };
+                    if ui.button(None, rtext) {
+                        actions.toggle_replay = true;
+                    }
+                    let ptext = match app.playback {
+                        None => "Play Replay",
+                        Some(_) => "Back to Live",
+                    };
+                    if ui.button(None, ptext) {
+                        actions.toggle_playback = true;
+                    }
+                });
+            });
+        }
+        actions
+    }
+
+    fn apply_actions(&mut self, app: &mut App, actions: &Actions, frame: &mut Frame) {
+        // anything replacing the image or the fires ends playback first
+        let (sw, sh) = (
+            screen_width() as usize / app.halves(),
+            screen_height() as usize,
+        );
+        let resizing = sw > 0 && sh > 0 && (sw != self.image.width() || sh != self.image.height());
+        if resizing
+            || actions.reseed
+            || actions.load_state
+            || actions.load_fires
+            || (actions.toggle_playback && app.playback.is_some())
+        {
+            if let Some((fires, base)) = app.playback.take() {
+                *self.fires.front_mut() = fires;
+                self.paint_fires_over(&base);
+            }
+        } else if actions.toggle_playback {
+            app.replay.recording = false;
+            app.replay.cursor = app.replay.buffer.len();
+            let base = self.image_without_fires();
+            let live_fires = std::mem::replace(self.fires.front_mut(), app.replay.last.clone());
+            self.paint_fires_over(&base);
+            app.playback = Some((live_fires, base));
+        }
+        if actions.toggle_replay {
+            match app.replay.recording {
+                false => app.replay.start(self.fires.read_front()),
+                true => app.replay.recording = false,
+            }
+        }
+
+        if resizing {
+            // frames of a different size can not go into the same gif
+            if let Some(Err(e)) = app.gif.take().map(GifRecorder::finish) {
+                macroquad::logging::warn!("could not finish gif: {}", e);
+            }
+            frame.resize = Some((sw, sh));
+        }
+        frame.reseed = actions.reseed;
+
+        if actions.toggle_gif {
+            app.gif = match app.gif.take() {
+                Some(g) => {
+                    if let Err(e) = g.finish() {
+                        macroquad::logging::warn!("could not finish gif: {}", e);
+                    }
+                    None
+                }
+                None => GifRecorder::new("forestfire.gif", self.image.width, self.image.height, 30)
+                    .map_err(|e| macroquad::logging::warn!("could not start gif: {}", e))
+                    .ok(),
+            };
+        }
+
+        if actions.save_config {
+            self.config.window_width = screen_width() as i32 / app.halves() as i32;
+            self.config.window_height = screen_height() as i32;
+            state::write_config(&self.config);
+        }
+
+        if actions.export_heatmap {
+            state::write_heatmap(&self.burn_count, self.image.width(), self.image.height());
+        }
+        if actions.export_fri {
+            state::export_fri(
+                &self.burn_count,
+                self.frno.saturating_sub(self.burns_since),
+                self.image.width(),
+                self.image.height(),
+                "fri.f32",
+            );
+        }
+
+        if actions.save_svg {
+            let colors: Vec<Color> = self
+                .fires
+                .read_front()
+                .iter()
+                .map(|f| fire_color(f, &self.config))
+                .collect();
+            let svg = export_svg(
+                &self.cellfield,
+                &self.image,
+                self.fires.read_front(),
+                &colors,
+            );
+            state::write_svg(&svg, self.frno);
+        }
+
+        if actions.save_state {
+            state::write_state(&self.snapshot().to_json());
+            state::write_events(
+                &serde_json::to_string(&self.event_log).expect("events are always serializable"),
+            );
+        }
+
+        if actions.save_fires {
+            if let Err(e) = state::save_fires(self.fires.read_front(), state::FIRES_FILE) {
+                macroquad::logging::warn!("could not save fires: {}", e);
+            }
+        }
+
+        if actions.load_fires {
+            let (w, h) = (self.image.width(), self.image.height());
+            match state::load_fires(state::FIRES_FILE, w, h) {
+                Ok(fires) => self.replace_fires(fires),
+                Err(e) => macroquad::logging::warn!("could not load fires: {}", e),
+            }
+        }
+
+        if actions.save_events {
+            state::write_events_csv(&events_csv(&self.event_log));
+        }
+
+        if actions.load_state {
+            match state::read_state().map(|s| Snapshot::from_json(&s)) {
+                Some(Ok(saved)) => self.restore(saved),
+                Some(Err(e)) => macroquad::logging::warn!("could not load state: {}", e),
+                None => {}
+            }
+        }
+    }
+
+    // panning and zooming the view, and the brushes, the rectangle, the flood fill
+    // and the tanker under the mouse or a touch
+    fn handle_pointer(&mut self, app: &mut App, rain: bool) {
+        let w = self.image.width();
+        let h = self.image.height();
+
+        let screen_mouse = Vec2::from(mouse_position());
+        let mut pan = Vec2::ZERO;
+        if is_mouse_button_down(MouseButton::Middle) {
+            pan += screen_mouse - app.prev_screen_mouse;
+        }
+        app.prev_screen_mouse = screen_mouse;
+        let mut pinch = None;
+        let mut tap = false;
+        match touches().as_slice() {
+            [a, b] => {
+                let curr = [a.position, b.position];
+                let lifted = [a, b]
+                    .iter()
+                    .any(|t| matches!(t.phase, TouchPhase::Ended | TouchPhase::Cancelled));
+                let (t0, start) = *app.two_touch_start.get_or_insert((get_time(), curr));
+                match lifted {
+                    false => {
+                        let dt = get_frame_time();
+                        if let TwoFingerGesture::Pinch { ratio, mid, pan: p } =
+                            classify_two_finger_gesture(app.prev_touch_positions, curr, dt)
+                        {
+                            pan += p;
+                            pinch = Some((ratio, mid));
+                        }
+                    }
+                    true => tap = is_two_finger_tap(start, curr, (get_time() - t0) as f32),
+                }
+                app.prev_touch_positions = Some(curr);
+            }
+            _ => {
+                app.prev_touch_positions = None;
+                app.two_touch_start = None;
+            }
+        }
+        app.two_finger_tap.set(tap);
+        // side by side simulations are not zoomed, each is drawn at its half
+        let view = match app.compare {
+            false => {
+                app.camera.update(pan, pinch);
+                app.camera
+            }
+            true => Camera {
+                offset: vec2(-((app.active * w) as f32), 0.),
+                zoom: 1.,
+            },
+        };
+        // no editing while a replay is shown
+        let cursor = match app.playback {
+            None => screen_to_cell(screen_mouse, &view, w, h),
+            Some(_) => None,
+        };
+
+        if rain {
+            if let Some((mx, my)) = cursor {
+                self.wet_field.set_disk(mx, my, 200);
+            }
+        }
+
+        // clicking with U held drops water from the tanker instead of painting
+        let tanker = is_key_down(KeyCode::U);
+        if let (true, true, Some((mx, my))) =
+            (tanker, is_mouse_button_pressed(MouseButton::Left), cursor)
+        {
+            if app.drops_remaining > 0 {
+                self.tanker_drop(mx, my, app.drop_radius as usize);
+                app.drops_remaining -= 1;
+            }
+        }
+
+        // right click toggles a single tree in tree mode, dragging on keeps setting or
+        // clearing cells, and in fire mode it puts out the nearest fire
+        if let (true, Some((mx, my))) = (is_mouse_button_pressed(MouseButton::Right), cursor) {
+            match app.brush.mode {
+                BrushMode::Tree => app.right_plant = !self.cellfield.get(mx, my),
+                BrushMode::Fire => {
+                    if let Some((x, y)) = extinguish_nearest(
+                        mx,
+                        my,
+                        self.fires.front_mut(),
+                        &self.cellfield,
+                        &mut self.image,
+                        self.alive_color,
+                    ) {
+                        self.dirty.add(x, y);
+                        log_event(
+                            &mut self.event_log,
+                            self.frno,
+                            SimEvent::FireExtinguished(x, y),
+                        );
+                    }
+                }
+                BrushMode::Erase | BrushMode::Rect | BrushMode::Flood | BrushMode::Water => {}
+            }
+        }
+
+        // left drag paints with the current brush along the cursor path
+        app.brush.radius = app.brush_radius as usize;
+        let stroke = match (
+            is_mouse_button_down(MouseButton::Left) && !tanker,
+            is_mouse_button_down(MouseButton::Right),
+            app.brush.mode,
+        ) {
+            (true, _, mode) => Some((mode, app.brush.radius)),
+            (false, true, BrushMode::Tree) if app.right_plant => Some((BrushMode::Tree, 0)),
+            (false, true, BrushMode::Tree) => Some((BrushMode::Erase, 0)),
+            (false, true, BrushMode::Erase) => Some((BrushMode::Erase, app.brush.radius)),
+            _ => None,
+        }
+        .filter(|_| !matches!(app.brush.mode, BrushMode::Rect | BrushMode::Flood));
+        // fire strokes are left out of undo, the fires burn the trees right away
+        let undoable = matches!(stroke, Some((BrushMode::Tree | BrushMode::Erase, _)));
+        match (undoable && cursor.is_some(), app.stroke_before.take()) {
+            (true, None) => app.stroke_before = Some(self.cellfield.clone()),
+            (true, before) => app.stroke_before = before,
+            (false, Some(before)) => {
+                undo::push_undo(UndoOp::between(&before, &self.cellfield), &mut app.undo)
+            }
+            (false, None) => {}
+        }
+        if let (Some((mode, radius)), Some((mx, my))) = (stroke, cursor) {
+            let tool = BrushTool { mode, radius };
+            let (px, py) = app.prev_mouse.unwrap_or((mx, my));
+            for_line(px, py, mx, my, |x, y| {
+                if mode == BrushMode::Water {
+                    self.paint_water(x, y, tool.radius);
+                    self.dirty.add_disk(x, y, tool.radius, w, h);
+                    return;
+                }
+                // in flash mode the brush lights every forest it touches
+                if mode == BrushMode::Fire && self.config.spread_mode == SpreadMode::FlashFire {
+                    self.flash_disk(x, y, tool.radius);
+                    return;
+                }
+                apply_brush(
+                    x,
+                    y,
+                    &tool,
+                    &mut self.cellfield,
+                    self.fires.front_mut(),
+                    &mut self.image,
+                    self.alive_color,
+                );
+                self.repave(x, y, tool.radius);
+                self.dirty.add_disk(x, y, tool.radius, w, h);
+            });
+            app.prev_mouse = Some((mx, my));
+        } else {
+            app.prev_mouse = None;
+        }
+
+        // left drag fills a rectangle with trees on release, right drag clears it
+        if app.brush.mode == BrushMode::Rect {
+            if is_mouse_button_pressed(MouseButton::Left)
+                || is_mouse_button_pressed(MouseButton::Right)
+            {
+                app.rect.anchor = cursor;
+            }
+            let released = match (
+                is_mouse_button_released(MouseButton::Left),
+                is_mouse_button_released(MouseButton::Right),
+            ) {
+                (true, _) => Some(true),
+                (false, true) => Some(false),
+                (false, false) => None,
+            };
+            if let (Some(set), Some((ax, ay)), Some((mx, my))) = (released, app.rect.anchor, cursor)
+            {
+                let (x0, x1) = (ax.min(mx), ax.max(mx));
+                let (y0, y1) = (ay.min(my), ay.max(my));
+                // with ctrl held the rectangle is copied instead
+                if ctrl_down() {
+                    app.rect.clip =
+                        Some(self.cellfield.get_region(x0, y0, x1 - x0 + 1, y1 - y0 + 1));
+                } else {
+                    let color = match set {
+                        true => self.alive_color,
+                        false => BLACK,
+                    };
+                    let before = self.cellfield.clone();
+                    cellfield_fill_rect(
+                        &mut self.cellfield,
+                        &mut self.image,
+                        x0,
+                        y0,
+                        x1,
+                        y1,
+                        set,
+                        color,
+                    );
+                    self.dirty.add(x0, y0);
+                    self.dirty.add(x1, y1);
+                    undo::push_undo(UndoOp::between(&before, &self.cellfield), &mut app.undo);
+                }
+            }
+            if released.is_some() {
+                app.rect.anchor = None;
+            }
+        } else {
+            app.rect.anchor = None;
+        }
+        // ctrl+v pastes the copied trees with their top left corner at the cursor
+        let paste = ctrl_down() && is_key_pressed(KeyCode::V);
+        if let (true, Some(clip), Some((mx, my))) = (paste, &app.rect.clip, cursor) {
+            let before = self.cellfield.clone();
+            self.paste(clip, mx, my);
+            undo::push_undo(UndoOp::between(&before, &self.cellfield), &mut app.undo);
+        }
+
+        // flood fill plants the empty region around the clicked cell in one go
+        let flood_click = is_mouse_button_pressed(MouseButton::Left)
+            || is_mouse_button_pressed(MouseButton::Middle);
+        if let (BrushMode::Flood, true, Some((mx, my))) = (app.brush.mode, flood_click, cursor) {
+            let color = self.alive_color;
+            let before = self.cellfield.clone();
+            if flood_fill_trees(&mut self.cellfield, &mut self.image, mx, my, color) > 0 {
+                self.dirty.add_all(w, h);
+                undo::push_undo(UndoOp::between(&before, &self.cellfield), &mut app.undo);
+            }
+        }
+
+        let touchcell = match touches().len() {
+            1 => screen_to_cell(touches()[0].position, &view, w, h),
+            _ => None,
+        };
+        if let Some((mx, my)) = touchcell {
+            let tool = BrushTool {
+                mode: BrushMode::Fire,
+                radius: 1,
+            };
+            apply_brush(
+                mx,
+                my,
+                &tool,
+                &mut self.cellfield,
+                self.fires.front_mut(),
+                &mut self.image,
+                self.alive_color,
+            );
+            self.dirty.add_disk(mx, my, tool.radius, w, h);
+        }
+        app.view = view;
+        app.cursor = cursor;
+    }
+
+    // everything drawn over the cells, from the density overlay up to the toasts
+    fn draw(&self, app: &mut App) {
+        let (w, h) = (self.image.width(), self.image.height());
+        let view = app.view;
+        let overlay = app.density_overlay && !app.compare;
+        if let (true, Some(_), Some((_, t))) = (overlay, &app.density_map, &app.dens) {
+            let dest = app.camera.dest(w, h);
+            draw_texture_ex(
+                *t,
+                dest.x,
+                dest.y,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(dest.size()),
+                    ..Default::default()
+                },
+            );
+        }
+        if let (Some((ax, ay)), Some((mx, my))) = (app.rect.anchor, app.cursor) {
+            let p0 = view.cell_to_screen(ax.min(mx), ay.min(my));
+            let p1 = view.cell_to_screen(ax.max(mx) + 1, ay.max(my) + 1);
+            let size = p1 - p0;
+            draw_rectangle_lines(p0.x, p0.y, size.x, size.y, 2., Color::new(1., 1., 1., 0.5));
+        }
+        draw_wind_arrow(&self.config);
+        if !app.no_ui && app.minimap_enabled && app.camera.zoom > 2. {
+            app.minimap.update(&self.image);
+            app.minimap.draw(&app.camera, w, h);
+        }
+        if !app.no_ui && app.species_legend {
+            draw_species_legend();
+        }
+        if !app.no_ui && app.showstats.get() {
+            draw_stats(
+                &self.stats,
+                self.frno,
+                self.config.eightconn,
+                self.config.grid_type,
+                app.frame_times.fps(),
+                app.target_fps,
+            );
+        }
+        if !app.no_ui && !app.popup_open {
+            if let Some((x, y)) = screen_to_cell(Vec2::from(mouse_position()), &view, w, h) {
+                let density = app.density_map.as_ref().map(|(map, _, _)| &map[..]);
+                draw_tooltip(
+                    x,
+                    y,
+                    &self.cellfield,
+                    self.fires.read_front(),
+                    density,
+                    &view,
+                );
+            }
+        }
+
+        if app.help_visible {
+            draw_help_overlay(screen_width(), screen_height());
+        }
+        if !app.no_ui {
+            app.tutorial.update(self.frno);
+            app.tutorial.draw();
+        }
+
+        if app.playback.is_some() {
+            let label = format!("REPLAY {}/{}", app.replay.cursor, app.replay.buffer.len());
+            let dims = measure_text(&label, None, 30, 1.0);
+            draw_text(&label, screen_width() - dims.width - 10., 60., 30., WHITE);
+        }
+        if app.paused {
+            let label = "|| PAUSED";
+            let dims = measure_text(label, None, 30, 1.0);
+            draw_text(label, screen_width() - dims.width - 10., 30., 30., WHITE);
+        }
+
+        if self.last_rain.is_some_and(|f| self.frno - f < 90) {
+            draw_text("RAIN", 10., 30., 30., SKYBLUE);
+        }
+
+        if let Some(t) = &mut app.toast {
+            draw_toast(t);
+            t.ttl -= 1;
+            if t.ttl == 0 {
+                app.toast = None;
+            }
+        }
+    }
+}
+
+// compared simulations restart together from the same seed
+fn reseed(instances: &mut [SimInstance]) {
+    let seed = miniquad::date::now().to_bits();
+    for inst in instances {
+        rand::srand(seed);
+        let mut config = inst.sim.config.clone();
+        config.seed = Some(seed);
+        let (sw, sh) = (inst.sim.image.width(), inst.sim.image.height());
+        let roads = std::mem::replace(&mut inst.sim.road_field, SparseCellField::new(0, 0));
+        let water = std::mem::replace(&mut inst.sim.water_field, SparseCellField::new(0, 0));
+        let trees = std::mem::replace(&mut inst.sim.cellfield, CellField::new(0, 0));
+        inst.sim = SimState::new(config, sw, sh);
+        inst.sim.road_field = roads;
+        inst.sim.water_field = water;
+        // a mapped tree field stays mapped
+        let fresh = std::mem::replace(&mut inst.sim.cellfield, trees);
+        inst.sim.cellfield.replace(fresh);
+        inst.sim.apply_barriers();
+    }
+}
+
+// the cells of the active simulation, or both halves when comparing, with the
+// heatmap, the shader or the texture
+fn draw_field(instances: &mut [SimInstance], renderer: &mut Option<ShaderRenderer>, app: &mut App) {
+    let active = app.active;
+    let sim = &mut instances[active].sim;
+    let (w, h) = (sim.image.width(), sim.image.height());
+    let camera = app.camera;
+    match renderer {
+        _ if app.compare => {
+            for (i, inst) in instances.iter_mut().enumerate() {
+                inst.draw(Rect::new((i * w) as f32, 0., w as f32, h as f32));
+            }
+            let mid = screen_width() / 2.;
+            draw_line(mid, 0., mid, screen_height(), 2., WHITE);
+            let faint = Color::new(1., 1., 1., 0.3);
+            draw_rectangle_lines((active * w) as f32, 0., w as f32, h as f32, 2., faint);
+            // how far the two forests have drifted apart
+            let (a, b) = (&instances[0].sim.cellfield, &instances[1].sim.cellfield);
+            let label = format!(
+                "{} cells differ, jaccard {:.3}",
+                hamming_distance(a, b),
+                jaccard_similarity(a, b)
+            );
+            let dims = measure_text(&label, None, 20, 1.);
+            draw_text(
+                &label,
+                mid - dims.width / 2.,
+                screen_height() - 10.,
+                20.,
+                WHITE,
+            );
+        }
+        _ if app.heatmap || app.fri_overlay => {
+            let (img, t) = app.heat.get_or_insert_with(|| {
+                let img = Image::gen_image_color(w as u16, h as u16, BLACK);
+                let t = Texture2D::from_image(&img);
+                t.set_filter(FilterMode::Nearest);
+                (img, t)
+            });
+            match app.fri_overlay {
+                false => paint_heatmap(&sim.burn_count, &sim.cellfield, img),
+                true => {
+                    let n_history = sim.frno.saturating_sub(sim.burns_since);
+                    paint_fri(&sim.burn_count, n_history, w, img);
+                }
+            }
+            t.update(img);
+            let dest = camera.dest(w, h);
+            draw_texture_ex(
+                *t,
+                dest.x,
+                dest.y,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(dest.size()),
+                    ..Default::default()
+                },
+            );
+            app.texture_stale = true;
+        }
+        _ if camera.zoom >= DIRECT_ZOOM => {
+            let viewport = (0., 0., screen_width(), screen_height());
+            draw_cells_direct(&sim.image, &camera, viewport, sim.config.grid_type);
+            app.texture_stale = true;
+        }
+        Some(r)
+            if sim.config.fire_color_mode == FireColorMode::Classic
+                && !sim.config.age_color
+                && !sim.config.perimeter_mode
+                && sim.road_field.count_set() == 0
+                && sim.water_field.count_set() == 0 =>
+        {
+            r.update(&sim.cellfield, sim.fires.read_front());
+            r.draw(&sim.config, sim.colorphase, camera.dest(w, h));
+            app.texture_stale = true;
+        }
+        _ => {
+            if app.texture_stale {
+                sim.dirty.add_all(w, h);
+                app.texture_stale = false;
+            }
+            instances[active].draw(camera.dest(w, h));
+        }
+    }
+}
+
+// the simulations the window starts with, two from the same seed for --compare
+fn start_instances(cli: &CliArgs) -> Vec<SimInstance> {
+    let mut config = initial_config(cli, true);
+
+    // compared simulations each get half of the window
+    let halves: usize = match cli.compare {
+        false => 1,
+        true => 2,
+    };
+    let w = screen_width() as usize / halves;
+    let h = screen_height() as usize;
+
+    // both simulations would write the same file
+    if cli.compare && cli.mmap_state.is_some() {
+        eprintln!("--mmap-state does not work with --compare");
+        exit(2);
+    }
+    if cli.compare && config.seed.is_none() {
+        config.seed = Some(miniquad::date::now().to_bits());
+    }
+    let mut instances: Vec<SimInstance> = (0..halves)
+        .map(|_| {
+            if let Some(seed) = config.seed {
+                rand::srand(seed);
+            }
+            SimInstance::new(SimState::new(config.clone(), w, h))
+        })
+        .collect();
+    #[cfg(not(target_arch = "wasm32"))]
+    for inst in &mut instances {
+        load_start_or_exit(&mut inst.sim, cli);
+    }
+    instances
+}
+
+// the window's main loop, until the user quits
+pub async fn run() {
+    let cli = parse_cli();
+    let mut instances = start_instances(&cli);
+    let (w, h) = (
+        instances[0].sim.image.width(),
+        instances[0].sim.image.height(),
+    );
+    // the shader only knows the classic two-state colors
+    let mut renderer = match cli.no_shader || cli.compare || cfg!(feature = "multistate") {
+        false => ShaderRenderer::new(w, h),
+        true => None,
+    };
+    let mut app = App::new(&cli, &instances[0].sim).await;
+
+    simulate_mouse_with_touch(false);
+
+    loop {
+        let frame_start = get_time();
+        app.frame_times.add(get_frame_time());
+        clear_background(BLACK);
+        for inst in &mut instances {
+            inst.sim.dirty = DirtyRect::new();
+        }
+        // clicking into a half of the window picks its simulation
+        if app.compare && is_mouse_button_pressed(MouseButton::Left) {
+            app.active = usize::from(mouse_position().0 >= screen_width() / 2.);
+        }
+        let active = app.active;
+
+        let mut frame = instances[active].sim.handle_input(&mut app);
+        if let Some((sw, sh)) = frame.resize {
+            for inst in &mut instances {
+                inst.resize(sw, sh);
+            }
+            if let Some(r) = &mut renderer {
+                r.resize(sw, sh);
+            }
+            app.resized();
+        }
+        if frame.reseed {
+            reseed(&mut instances);
+        }
+
+        let nsteps = app.step(&mut instances[active].sim, &mut frame);
+        for (i, inst) in instances.iter_mut().enumerate() {
+            if i != active {
+                for _ in 0..nsteps {
+                    inst.sim.step();
+                }
+            }
+        }
+
+        draw_field(&mut instances, &mut renderer, &mut app);
+        app.update(&mut instances[active].sim, &frame);
+        instances[active].sim.draw(&mut app);
+
+        // the rest of the frame is slept away, vsync may cap it lower anyway
+        // and the browser paces the web build itself
+        let left = 1. / app.target_fps as f64 - (get_time() - frame_start);
+        if !cfg!(target_arch = "wasm32") && left > 0. {
+            std::thread::sleep(std::time::Duration::from_secs_f64(left));
+        }
+
+        next_frame().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::Mutex;
+
+    // rand is global, tests that seed it hold this so runs repeat
+    static SEEDED: Mutex<()> = Mutex::new(());
+
+    // the fields fire spread reads besides the trees, dry, grown and without species
+    struct Ground {
+        wet: CellField,
+        #[cfg(not(feature = "multistate"))]
+        seedlings: CellField,
+        #[cfg(feature = "multistate")]
+        stages: PackedField<2>,
+        species: Vec<u8>,
+        cooldown: Vec<u16>,
+    }
+
+    impl Ground {
+        fn new(cf: &CellField) -> Ground {
+            Ground {
+                wet: CellField::new(cf.w, cf.h),
+                #[cfg(not(feature = "multistate"))]
+                seedlings: CellField::new(cf.w, cf.h),
+                #[cfg(feature = "multistate")]
+                stages: stages_from(cf),
+                species: vec![NO_SPECIES; cf.w * cf.h],
+                cooldown: vec![0; cf.w * cf.h],
+            }
+        }
+        fn fields(&self) -> SpreadFields<'_> {
+            SpreadFields {
+                wet_field: &self.wet,
+                #[cfg(not(feature = "multistate"))]
+                seedling_field: &self.seedlings,
+                #[cfg(feature = "multistate")]
+                stages: &self.stages,
+                species_field: &self.species,
+                cooldown: &self.cooldown,
+            }
+        }
+    }
+
+    // runs only the spread for steps steps from fires, clearing the trees they
+    // ignite, returns the last front
+    fn spread(cf: &mut CellField, fires: Vec<Fire>, config: &SimConfig, steps: usize) -> Vec<Fire> {
+        let ground = Ground::new(cf);
+        let pcfg = PropagateConfig::new(config);
+        let mut front = fires;
+        for step in 0..steps {
+            front = propagate_fires(&front, cf, &ground.fields(), &pcfg, step as u64);
+            for f in &front {
+                cf.clr(f.x, f.y);
+            }
+        }
+        front
+    }
+
+    #[test]
+    fn resize_keeps_overlap() {
+        let mut cf = CellField::new(100, 100);
+        for i in 0..100 {
+            cf.set(i, i);
+            cf.set(99 - i, i);
+        }
+        cf.resize(80, 80);
+        assert_eq!((cf.w, cf.h), (80, 80));
+        for y in 0..80 {
+            for x in 0..80 {
+                assert_eq!(cf.get(x, y), x == y || x == 99 - y, "({}, {})", x, y);
+            }
+        }
+        assert_eq!(cf.count_set(), 80 + 60);
+    }
+
+    #[test]
+    fn torus_wraps_spread() {
+        let config = SimConfig {
+            torus: true,
+            ..SimConfig::new()
+        };
+        let mut cf = CellField::new(10, 10);
+        cf.fill_rect(0, 0, 9, 9);
+        cf.clr(0, 5);
+        spread(&mut cf, vec![Fire::new(0, 5, 1.0)], &config, 10);
+        assert!(!cf.get(9, 5));
+        // one step is enough across the edge, and not without the wrap
+        let mut cf = CellField::new(10, 10);
+        cf.fill_rect(0, 0, 9, 9);
+        spread(&mut cf, vec![Fire::new(0, 5, 1.0)], &config, 1);
+        assert!(!cf.get(9, 5));
+        let mut cf = CellField::new(10, 10);
+        cf.fill_rect(0, 0, 9, 9);
+        spread(&mut cf, vec![Fire::new(0, 5, 1.0)], &SimConfig::new(), 1);
+        assert!(cf.get(9, 5));
+    }
+
+    #[test]
+    fn torus_corners_are_neighbors() {
+        let mut cf = CellField::new(10, 7);
+        for (x, y) in [(9, 0), (0, 6), (9, 6)] {
+            cf.set(x, y);
+        }
+        assert_eq!(cf.neighbor_count_wrapping(0, 0, false), 2);
+        assert_eq!(cf.neighbor_count_wrapping(0, 0, true), 3);
+        assert_eq!(cf.neighbor_count(0, 0, true), 0);
+        cf.set(0, 0);
+        assert_eq!(cf.neighbor_count_wrapping(9, 6, true), 3);
+    }
+
+    #[test]
+    fn neighbor_count_matches_naive() {
+        let four = [[-1, 0], [1, 0], [0, -1], [0, 1]];
+        for bits in 0..512u32 {
+            let mut cf = CellField::new(3, 3);
+            for i in 0..9 {
+                if bits >> i & 1 != 0 {
+                    cf.set(i % 3, i / 3);
+                }
+            }
+            for (x, y) in (0..9).map(|i| (i % 3, i / 3)) {
+                for (eight, wrap) in [(false, false), (true, false), (false, true), (true, true)] {
+                    let dirs = match eight {
+                        false => &four[..],
+                        true => &NGH[..],
+                    };
+                    let naive = dirs
+                        .iter()
+                        .filter(|d| {
+                            let (nx, ny) = (x as i32 + d[0], y as i32 + d[1]);
+                            match wrap {
+                                true => {
+                                    cf.get(nx.rem_euclid(3) as usize, ny.rem_euclid(3) as usize)
+                                }
+                                false => {
+                                    (0..3).contains(&nx)
+                                        && (0..3).contains(&ny)
+                                        && cf.get(nx as usize, ny as usize)
+                                }
+                            }
+                        })
+                        .count() as u8;
+                    let n = match wrap {
+                        false => cf.neighbor_count(x, y, eight),
+                        true => cf.neighbor_count_wrapping(x, y, eight),
+                    };
+                    assert_eq!(
+                        n, naive,
+                        "bits {:09b} at ({}, {}), eight {}, wrap {}",
+                        bits, x, y, eight, wrap
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cellfield_json_round_trip() {
+        let mut cf = CellField::new(37, 19);
+        for i in 0..200 {
+            cf.set(i * 7 % 37, i * 11 % 19);
+        }
+        let json = serde_json::to_string(&cf).unwrap();
+        let back: CellField = serde_json::from_str(&json).unwrap();
+        assert_eq!((back.w, back.h), (37, 19));
+        assert_eq!(&back.arr[..], &cf.arr[..]);
+        // a snapshot of a running simulation keeps its trees and fires
+        let mut sim = SimState::new(SimConfig::new(), 60, 40);
+        for _ in 0..20 {
+            sim.step();
+        }
+        let saved = Snapshot::from_json(&sim.snapshot().to_json()).unwrap();
+        assert_eq!(&saved.cellfield.arr[..], &sim.cellfield.arr[..]);
+        assert_eq!(saved.fires.len(), sim.fires.read_front().len());
+        assert_eq!(saved.frno, sim.frno);
+        // words of the wrong length are refused
+        let short = json.replacen("\"arr\":\"", "\"arr\":\"AAAA", 1);
+        assert!(serde_json::from_str::<CellField>(&short).is_err());
+    }
+
+    #[test]
+    fn tooltip_density_is_the_box_map() {
+        let mut cf = CellField::new(20, 20);
+        cf.fill_rect(0, 0, 9, 19);
+        let map = cf.density_map_box(2);
+        // two of the five columns around x = 10 hold trees
+        assert!((map[5 * 20 + 10] - 0.4).abs() < 1e-6);
+        assert_eq!(map[5 * 20 + 2], 1.);
+        let mut fire = Fire::new(3, 4, 1.0);
+        fire.age = 7;
+        assert_eq!(
+            format!("{:?}", get_cell_state(3, 4, &cf, &[fire])),
+            "burning(age=7)"
+        );
+        assert_eq!(format!("{:?}", get_cell_state(15, 4, &cf, &[])), "empty");
+    }
+
+    #[test]
+    fn count_set_and_clr_add_up() {
+        for (w, h) in [(1, 1), (7, 3), (8, 8), (9, 17), (63, 65), (100, 1)] {
+            let mut cf = not(&CellField::new(w, h));
+            assert_eq!(cf.count_set(), w * h);
+            assert_eq!(cf.count_clr(), 0);
+            for i in 0..w * h / 3 {
+                cf.clr(i * 3 % w, i * 3 / w);
+            }
+            assert_eq!(cf.count_set() + cf.count_clr(), w * h, "{}x{}", w, h);
+            assert!((cf.density() - cf.count_set() as f64 / (w * h) as f64).abs() < 1e-12);
+            // padding bits of edge tiles are not counted
+            cf.arr.fill(u64::MAX);
+            assert_eq!(cf.count_set(), w * h);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn rects_match_cell_by_cell(
+            (w, h) in (1usize..120, 1usize..120),
+            rects in prop::collection::vec(any::<(usize, usize, usize, usize, bool)>(), 1..6),
+        ) {
+            let mut words = CellField::new(w, h);
+            let mut cells = CellField::new(w, h);
+            for (a, b, c, d, set) in rects {
+                let (xa, xb, ya, yb) = (a % w, c % w, b % h, d % h);
+                let (x0, x1, y0, y1) = (xa.min(xb), xa.max(xb), ya.min(yb), ya.max(yb));
+                match set {
+                    true => words.fill_rect(x0, y0, x1, y1),
+                    false => words.clear_rect(x0, y0, x1, y1),
+                }
+                for y in y0..=y1 {
+                    for x in x0..=x1 {
+                        match set {
+                            true => cells.set(x, y),
+                            false => cells.clr(x, y),
+                        }
+                    }
+                }
+            }
+            prop_assert_eq!(&words.arr[..], &cells.arr[..]);
+        }
+
+        #[test]
+        fn set_then_get((w, h) in (1usize..200, 1usize..200), pts in cells_in(200)) {
+            let mut cf = CellField::new(w, h);
+            for (x, y) in pts.into_iter().map(|(x, y)| (x % w, y % h)) {
+                cf.set(x, y);
+                prop_assert!(cf.get(x, y));
+                cf.clr(x, y);
+                prop_assert!(!cf.get(x, y));
+            }
+        }
+
+        #[test]
+        fn set_and_clr_cover_grid((w, h) in (1usize..200, 1usize..200), pts in cells_in(200)) {
+            let cf = field_with(w, h, &pts);
+            prop_assert_eq!(cf.count_set() + cf.count_clr(), w * h);
+        }
+
+        #[test]
+        fn rect_fill_clear_idempotent(
+            (w, h) in (1usize..200, 1usize..200),
+            pts in cells_in(200),
+            (a, b, c, d) in any::<(usize, usize, usize, usize)>(),
+        ) {
+            let (xa, xb, ya, yb) = (a % w, c % w, b % h, d % h);
+            let (x0, x1, y0, y1) = (xa.min(xb), xa.max(xb), ya.min(yb), ya.max(yb));
+            let mut cf = field_with(w, h, &pts);
+            cf.fill_rect(x0, y0, x1, y1);
+            cf.clear_rect(x0, y0, x1, y1);
+            let once = cf.clone();
+            cf.fill_rect(x0, y0, x1, y1);
+            cf.clear_rect(x0, y0, x1, y1);
+            prop_assert_eq!(&cf.arr[..], &once.arr[..]);
+        }
+
+        #[test]
+        fn not_not_is_same((w, h) in (1usize..200, 1usize..200), pts in cells_in(200)) {
+            let cf = field_with(w, h, &pts);
+            prop_assert_eq!(&not(&not(&cf)).arr[..], &cf.arr[..]);
+        }
+
+        #[test]
+        fn and_counts_at_most_either(
+            (w, h) in (1usize..200, 1usize..200),
+            pa in cells_in(200),
+            pb in cells_in(200),
+        ) {
+            let (a, b) = (field_with(w, h, &pa), field_with(w, h, &pb));
+            prop_assert!(and(&a, &b).count_set() <= a.count_set().min(b.count_set()));
+        }
+
+        #[test]
+        fn de_morgan_and_commutative(
+            (w, h) in (1usize..200, 1usize..200),
+            pa in cells_in(200),
+            pb in cells_in(200),
+        ) {
+            let (a, b) = (field_with(w, h, &pa), field_with(w, h, &pb));
+            prop_assert_eq!(&(!&(&a & &b)).arr[..], &(&!&a | &!&b).arr[..]);
+            prop_assert_eq!(&(!&(&a | &b)).arr[..], &(&!&a & &!&b).arr[..]);
+            prop_assert_eq!(&and(&a, &b).arr[..], &and(&b, &a).arr[..]);
+            prop_assert_eq!(&or(&a, &b).arr[..], &or(&b, &a).arr[..]);
+            prop_assert_eq!(&xor(&a, &b).arr[..], &xor(&b, &a).arr[..]);
+            let mut c = a.clone();
+            c ^= &b;
+            prop_assert_eq!(&c.arr[..], &xor(&a, &b).arr[..]);
+        }
+
+        #[test]
+        fn distance_to_self_and_complement(
+            (w, h) in (1usize..200, 1usize..200),
+            pts in cells_in(200),
+        ) {
+            let a = field_with(w, h, &pts);
+            prop_assert_eq!(hamming_distance(&a, &a.clone()), 0);
+            prop_assert_eq!(jaccard_similarity(&a, &a.clone()), 1.0);
+            prop_assert_eq!(hamming_distance(&a, &not(&a)), w * h);
+            prop_assert_eq!(jaccard_similarity(&a, &not(&a)), 0.0);
+        }
+    }
+
+    // up to n cells, wrapped into the grid by field_with
+    fn cells_in(n: usize) -> impl Strategy<Value = Vec<(usize, usize)>> {
+        prop::collection::vec(any::<(usize, usize)>(), 0..n)
+    }
+
+    fn field_with(w: usize, h: usize, pts: &[(usize, usize)]) -> CellField {
+        let mut cf = CellField::new(w, h);
+        for &(x, y) in pts {
+            cf.set(x % w, y % h);
+        }
+        cf
+    }
+
+    #[test]
+    fn firebreak_clears_disk() {
+        let mut cf = CellField::new(40, 30);
+        cf.fill_rect(0, 0, 39, 29);
+        let mut img = Image::gen_image_color(40, 30, GREEN);
+        draw_firebreak(20, 3, 5, &mut cf, &mut img);
+        for y in 0..30usize {
+            for x in 0..40usize {
+                let (dx, dy) = (x.abs_diff(20), y.abs_diff(3));
+                let inside = dx * dx + dy * dy <= 25;
+                assert_eq!(cf.get(x, y), !inside, "({}, {})", x, y);
+                if inside {
+                    assert_eq!(img.get_pixel(x as u32, y as u32), BLACK);
+                }
+            }
+        }
+    }
+
+    // what --seed promises, two runs from one seed draw the same frame 100, and
+    // a run from another seed does not
+    #[test]
+    fn same_seed_same_image() {
+        let _seeded = SEEDED.lock().unwrap();
+        let run = |seed| {
+            rand::srand(seed);
+            let mut sim = SimState::new(SimConfig::new(), 200, 150);
+            for _ in 0..100 {
+                sim.step();
+            }
+            sim
+        };
+        let (a, b, c) = (run(7), run(7), run(8));
+        assert_eq!(a.frno, 100);
+        assert!(a.image.bytes == b.image.bytes);
+        assert_eq!(hamming_distance(&a.cellfield, &b.cellfield), 0);
+        assert!(hamming_distance(&a.cellfield, &c.cellfield) > 0);
+    }
+
+    #[test]
+    fn no_duplicate_fires() {
+        let _seeded = SEEDED.lock().unwrap();
+        rand::srand(43);
+        let mut sim = SimState::new(SimConfig::new(), 200, 150);
+        let mut burned = 0;
+        for step in 0..100 {
+            sim.step();
+            burned += sim.fires.read_front().len();
+            let mut seen = std::collections::HashSet::new();
+            for f in sim.fires.read_front() {
+                assert!(
+                    seen.insert((f.x, f.y)),
+                    "({}, {}) twice at {}",
+                    f.x,
+                    f.y,
+                    step
+                );
+            }
+        }
+        assert!(burned > 0);
+    }
+
+    #[test]
+    fn two_finger_gestures() {
+        let (a, b) = (vec2(100., 100.), vec2(200., 100.));
+        let down = classify_two_finger_gesture(None, [a, b], 0.016);
+        assert!(matches!(down, TwoFingerGesture::Start));
+        let apart =
+            classify_two_finger_gesture(Some([a, b]), [vec2(50., 110.), vec2(250., 110.)], 0.016);
+        let TwoFingerGesture::Pinch { ratio, mid, pan } = apart else {
+            panic!("spreading fingers should pinch");
+        };
+        assert!((ratio - 2.).abs() < 1e-5);
+        assert_eq!(mid, vec2(150., 110.));
+        assert_eq!(pan, vec2(0., 10.));
+        let stale = classify_two_finger_gesture(Some([a, b]), [a, b], TAP_SECONDS);
+        assert!(matches!(stale, TwoFingerGesture::Start));
+        let nudged = [a + vec2(5., 5.), b - vec2(5., 0.)];
+        assert!(is_two_finger_tap([a, b], nudged, 0.1));
+        assert!(!is_two_finger_tap([a, b], nudged, TAP_SECONDS));
+        assert!(!is_two_finger_tap([a, b], [a, b + vec2(TAP_SLOP, 0.)], 0.1));
+    }
+
+    #[test]
+    fn classic_preset_is_the_original() {
+        let presets = &*PRESETS;
+        let (name, classic) = &presets[0];
+        assert_eq!(name, "Classic");
+        let json = |c: &SimConfig| serde_json::to_string(c).unwrap();
+        assert_eq!(json(classic), json(&SimConfig::new()));
+        // the sliders main started with
+        assert_eq!(classic.logfireprob, 1e-6f32.log10());
+        assert_eq!(classic.logtreeprob, 1e-3f32.log10());
+        assert_eq!((classic.colorspeed, classic.firemaxage), (5., 10.));
+        assert!(!classic.eightconn);
+        let mut config = SimConfig::new();
+        config.apply_preset(&presets[1].1);
+        config.apply_preset(classic);
+        assert_eq!(json(&config), json(&SimConfig::new()));
+    }
+
+    #[test]
+    fn poisson_mean_is_avgper() {
+        let _seeded = SEEDED.lock().unwrap();
+        rand::srand(42);
+        let mut proc = PoissonProcess::new();
+        let total: usize = (0..100_000).map(|_| proc.draw(1.0)).sum();
+        let mean = total as f64 / 100_000.;
+        assert!((0.99..=1.01).contains(&mean), "mean {}", mean);
+    }
+
+    #[test]
+    fn poisson_rare_events() {
+        let _seeded = SEEDED.lock().unwrap();
+        rand::srand(42);
+        let mut proc = PoissonProcess::new();
+        let hits = (0..1_000_000).filter(|_| proc.draw(0.001) > 0).count();
+        let frac = hits as f64 / 1_000_000.;
+        assert!((frac - 0.001).abs() <= 0.0002, "fraction {}", frac);
+    }
+
+    #[test]
+    fn poisson_accumulates_large_rates() {
+        let _seeded = SEEDED.lock().unwrap();
+        rand::srand(42);
+        let mut proc = PoissonProcess::new();
+        let total: usize = (0..10).map(|_| proc.draw(100.0)).sum();
+        assert!(total > 500, "total {}", total);
+    }
+
+    #[test]
+    fn poisson_zero_rate_never_fires() {
+        let mut proc = PoissonProcess::new();
+        proc.draw(0.5);
+        assert!((0..1000).all(|_| proc.draw(0.0) == 0));
+    }
+
+    // a DebounceToggle on a key the test presses and releases
+    fn key_toggle() -> (Rc<Cell<bool>>, DebounceToggle<impl Fn() -> bool>) {
+        let down = Rc::new(Cell::new(false));
+        let key = down.clone();
+        (down, DebounceToggle::new(move || key.get()))
+    }
+
+    fn timed_toggle(
+        hold: usize,
+        release: usize,
+    ) -> (Rc<Cell<bool>>, DebounceToggle<impl Fn() -> bool>) {
+        let down = Rc::new(Cell::new(false));
+        let key = down.clone();
+        (
+            down,
+            DebounceToggle::with_timing(move || key.get(), hold, release),
+        )
+    }
+
+    #[test]
+    fn debounce_transitions() {
+        let (down, mut t) = key_toggle();
+        // press, release: on
+        down.set(true);
+        assert!(!t.get());
+        assert_eq!(t.state, 1);
+        down.set(false);
+        assert!(t.get());
+        assert_eq!(t.state, 2);
+        // press, release: off again
+        down.set(true);
+        assert!(!t.get());
+        assert_eq!(t.state, 3);
+        down.set(false);
+        assert!(!t.get());
+        assert_eq!(t.state, 0);
+    }
+
+    #[test]
+    fn debounce_held_key_toggles_once() {
+        let (down, mut t) = key_toggle();
+        let mut outputs = Vec::new();
+        for _ in 0..3 {
+            down.set(true);
+            for _ in 0..10 {
+                outputs.push(t.get());
+                assert!(t.state == 1 || t.state == 3, "state {}", t.state);
+            }
+            down.set(false);
+            outputs.push(t.get());
+        }
+        // only the release after the first and third hold turn it on
+        let on: Vec<usize> = (0..outputs.len()).filter(|&i| outputs[i]).collect();
+        assert_eq!(on, vec![10, 32]);
+    }
+
+    #[test]
+    fn debounce_reset_turns_off() {
+        for steps in 0..4 {
+            let (down, mut t) = key_toggle();
+            for i in 0..steps {
+                down.set(i % 2 == 0);
+                t.get();
+            }
+            assert_eq!(t.state, steps);
+            t.reset();
+            assert_eq!(t.state, 0);
+            down.set(false);
+            assert!(!t.get());
+        }
+    }
+
+    // the cells within distance n of (5, 5) burn in n steps of certain spread,
+    // manhattan with 4 neighbors and chebyshev with 8
+    #[test]
+    fn spread_reaches_distance() {
+        for (eightconn, steps) in [(false, 5), (true, 5), (true, 3)] {
+            let config = SimConfig {
+                eightconn,
+                spread_rule: SpreadRule::Deterministic,
+                ..SimConfig::new()
+            };
+            let mut cf = CellField::new(10, 10);
+            cf.fill_rect(0, 0, 9, 9);
+            cf.clr(5, 5);
+            let front = spread(&mut cf, vec![Fire::new(5, 5, 1.0)], &config, steps);
+            assert!(!front.is_empty());
+            for y in 0..10usize {
+                for x in 0..10usize {
+                    let (dx, dy) = (x.abs_diff(5), y.abs_diff(5));
+                    let dist = match eightconn {
+                        false => dx + dy,
+                        true => dx.max(dy),
+                    };
+                    assert_eq!(
+                        cf.get(x, y),
+                        dist > steps,
+                        "({}, {}) eightconn {}",
+                        x,
+                        y,
+                        eightconn
+                    );
+                }
+            }
+            let mut cells: Vec<(usize, usize)> = front.iter().map(|f| (f.x, f.y)).collect();
+            cells.sort();
+            cells.dedup();
+            assert_eq!(cells.len(), front.len());
+        }
+    }
+
+    #[test]
+    fn certain_extinction_leaves_only_new_fires() {
+        let _seeded = SEEDED.lock().unwrap();
+        rand::srand(60);
+        let config = SimConfig {
+            extinction_prob: 1.0,
+            ..SimConfig::new()
+        };
+        let mut sim = SimState::new(config, 100, 100);
+        let trees: Vec<(usize, usize)> = sim.cellfield.iter_set().take(50).collect();
+        for (x, y) in trees {
+            sim.cellfield.clr(x, y);
+            sim.fires.front_mut().push(Fire::new(x, y, 1.0));
+        }
+        let mut spread = 0;
+        for _ in 0..20 {
+            sim.step();
+            spread += sim.fires.read_front().len();
+            assert!(sim.fires.read_front().iter().all(|f| f.age == 0));
+        }
+        assert!(spread > 0);
+    }
+
+    // two fires with a tree between them: any spread burns a diamond, half chance
+    // a ragged part of it, two neighbors only the gap, three nothing
+    #[test]
+    fn spread_rules_differ() {
+        let rules = [
+            SpreadRule::Deterministic,
+            SpreadRule::Independent(0.5),
+            SpreadRule::Majority(2),
+            SpreadRule::Threshold(0.75),
+        ];
+        let burned: Vec<Vec<(usize, usize)>> = rules
+            .iter()
+            .map(|&spread_rule| {
+                let config = SimConfig {
+                    spread_rule,
+                    ..SimConfig::new()
+                };
+                let mut cf = CellField::new(40, 40);
+                cf.fill_rect(0, 0, 39, 39);
+                cf.clr(19, 20);
+                cf.clr(21, 20);
+                let fires = vec![Fire::new(19, 20, 1.0), Fire::new(21, 20, 1.0)];
+                spread(&mut cf, fires, &config, 10);
+                (!&cf).iter_set().collect()
+            })
+            .collect();
+        assert_eq!(burned[2], vec![(19, 20), (20, 20), (21, 20)]);
+        assert_eq!(burned[3], vec![(19, 20), (21, 20)]);
+        assert!(burned[1].len() < burned[0].len());
+        for i in 0..rules.len() {
+            for j in i + 1..rules.len() {
+                assert_ne!(burned[i], burned[j], "rules {} and {}", i, j);
+            }
+        }
+    }
+
+    #[test]
+    fn debounce_one_one_is_new() {
+        let (a, mut new) = key_toggle();
+        let (b, mut timed) = timed_toggle(1, 1);
+        for i in 0..200u32 {
+            let down = i.wrapping_mul(2_654_435_761) >> 29 & 1 != 0;
+            a.set(down);
+            b.set(down);
+            assert_eq!(new.get(), timed.get(), "frame {}", i);
+            assert_eq!(new.state, timed.state);
+        }
+    }
+
+    #[test]
+    fn debounce_counts_hold_and_release() {
+        for (hold, release) in [(1, 1), (2, 1), (1, 3), (4, 2)] {
+            let (down, mut t) = timed_toggle(hold, release);
+            // a press one frame short does nothing
+            down.set(true);
+            for _ in 1..hold {
+                assert!(!t.get());
+            }
+            down.set(false);
+            assert!(!t.get());
+            assert_eq!(t.state, 0, "hold {} release {}", hold, release);
+            // a full press and release turns it on on the last release frame
+            down.set(true);
+            for _ in 0..hold {
+                assert!(!t.get());
+            }
+            assert_eq!(t.state, 1);
+            down.set(false);
+            for _ in 1..release {
+                assert!(!t.get());
+            }
+            assert!(t.get(), "hold {} release {}", hold, release);
+            // and the same again turns it off
+            down.set(true);
+            for _ in 1..hold {
+                assert!(t.get());
+            }
+            assert!(!t.get());
+            down.set(false);
+            for _ in 0..release {
+                t.get();
+            }
+            assert_eq!(t.state, 0);
+        }
+    }
+
+    // five seconds of frames at any rate the accumulator keeps up with step 60
+    // times a second
+    #[test]
+    fn ticks_follow_wall_clock() {
+        for hz in [30., 60., 144., 240.] {
+            let mut sim = SimState::new(SimConfig::new(), 20, 20);
+            let mut dt_accum = 0.;
+            for _ in 0..(5. * hz) as usize {
+                for _ in 0..take_ticks(&mut dt_accum, 1. / hz) {
+                    sim.step();
+                }
+            }
+            assert!(
+                (295..=305).contains(&sim.frno),
+                "{} Hz: {} steps",
+                hz,
+                sim.frno
+            );
+        }
+        // a frame too long to catch up on runs MAX_SUBSTEPS and forgets the rest
+        let mut dt_accum = 0.;
+        assert_eq!(take_ticks(&mut dt_accum, 1.), MAX_SUBSTEPS);
+        assert_eq!(dt_accum, 0.);
+    }
+
+    #[test]
+    fn components_biggest_first() {
+        let mut cf = CellField::new(12, 8);
+        // a plus of 5, a bar of 3 and a single cell touching the bar corner to corner
+        for (x, y) in [
+            (2, 1),
+            (1, 2),
+            (2, 2),
+            (3, 2),
+            (2, 3),
+            (7, 5),
+            (8, 5),
+            (9, 5),
+            (10, 6),
+        ] {
+            cf.set(x, y);
+        }
+        let comps = cf.connected_components_4();
+        let sizes: Vec<usize> = comps.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![5, 3, 1]);
+        assert!(comps[1].contains(&(9, 5)));
+        assert_eq!(comps[2], vec![(10, 6)]);
+        assert_eq!(cf.largest_component_size(), 5);
+        assert_eq!(CellField::new(5, 5).largest_component_size(), 0);
+        cf.fill_rect(0, 0, 11, 7);
+        assert_eq!(cf.largest_component_size(), 96);
+    }
+
+    #[test]
+    fn road_stops_fire() {
+        let _seeded = SEEDED.lock().unwrap();
+        rand::srand(77);
+        // certain spread, nothing wet, no new trees, sparks, embers or rain
+        let config = SimConfig {
+            spread_rule: SpreadRule::Deterministic,
+            logfireprob: -10.,
+            logtreeprob: -10.,
+            logwetness: -10.,
+            logemberprob: -20.,
+            lograinprob: -20.,
+            logdroughtprob: -20.,
+            ..SimConfig::new()
+        };
+        let mut sim = SimState::new(config, 200, 200);
+        sim.cellfield.fill_rect(0, 0, 199, 199);
+        rasterize_roads(
+            &[(0, 100, 199, 100)],
+            3,
+            &mut sim.road_field,
+            &mut sim.image,
+        );
+        sim.apply_barriers();
+        for x in 0..200 {
+            sim.cellfield.clr(x, 10);
+            sim.fires.front_mut().push(Fire::new(x, 10, 1.0));
+        }
+        for _ in 0..300 {
+            sim.step();
+        }
+        assert!(sim.fires.read_front().is_empty());
+        for x in 0..200 {
+            assert!(!sim.cellfield.get(x, 96), "({}, 96) did not burn", x);
+            assert!(sim.road_field.get(x, 100));
+            for y in 104..200 {
+                assert!(
+                    sim.cellfield.get(x, y),
+                    "fire crossed the road to ({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn window_title_format() {
+        let mut stats = SimStats::new();
+        stats.density = 0.4734;
+        stats.fire_count = 234;
+        let title = format_window_title(&stats, 1234);
+        assert_eq!(
+            title,
+            "Forest Fires | Trees: 47.3% | Fires: 234 | Frame: 1234"
+        );
+        let fields: Vec<&str> = title.split(" | ").collect();
+        assert_eq!(
+            fields[1]
+                .trim_start_matches("Trees: ")
+                .trim_end_matches('%')
+                .parse(),
+            Ok(47.3)
+        );
+        assert_eq!(fields[2].trim_start_matches("Fires: ").parse(), Ok(234));
+        assert_eq!(fields[3].trim_start_matches("Frame: ").parse(), Ok(1234));
+    }
+
+    #[test]
+    fn region_blit_round_trip() {
+        let _seeded = SEEDED.lock().unwrap();
+        rand::srand(88);
+        let mut cf = CellField::new(100, 100);
+        for y in 0..100 {
+            for x in 0..100 {
+                if rand::gen_range(0, 2) == 1 {
+                    cf.set(x, y);
+                }
+            }
+        }
+        let region = cf.get_region(35, 60, 30, 30);
+        assert_eq!((region.w, region.h), (30, 30));
+        let mut pasted = CellField::new(100, 100);
+        pasted.blit(&region, 35, 60);
+        for y in 0..100 {
+            for x in 0..100 {
+                let inside = (35..65).contains(&x) && (60..90).contains(&y);
+                assert_eq!(pasted.get(x, y), inside && cf.get(x, y), "({}, {})", x, y);
+            }
+        }
+        // cut off at the edge
+        let corner = cf.get_region(80, 90, 30, 30);
+        assert_eq!((corner.w, corner.h), (20, 10));
+    }
+}
@@ -0,0 +1,96 @@
+#[cfg(feature = "mmap-state")]
+use memmap2::MmapMut;
+
+// words before the cells in a mapped file, the grid width and height
+#[cfg(feature = "mmap-state")]
+const HEADER_WORDS: usize = 2;
+
+// the words of a CellField, on the heap or in a file mapped into memory. The
+// file holds the width, the height and the words in native byte order, and
+// keeps the last cells written when the program ends, however it ends
+pub enum MmapVec {
+    Heap(Vec<u64>),
+    #[cfg(feature = "mmap-state")]
+    Mapped(MmapMut),
+}
+
+// mappings start on a page boundary, so the bytes are aligned for u64
+#[cfg(feature = "mmap-state")]
+fn as_words(map: &MmapMut) -> &[u64] {
+    unsafe { std::slice::from_raw_parts(map.as_ptr() as *const u64, map.len() / 8) }
+}
+
+#[cfg(feature = "mmap-state")]
+fn as_words_mut(map: &mut MmapMut) -> &mut [u64] {
+    unsafe { std::slice::from_raw_parts_mut(map.as_mut_ptr() as *mut u64, map.len() / 8) }
+}
+
+impl MmapVec {
+    // maps path, created or resized to len words for a w x h grid. A file
+    // already written for that grid keeps its cells, anything else starts clear
+    #[cfg(feature = "mmap-state")]
+    pub fn map_file(path: &str, w: usize, h: usize, len: usize) -> std::io::Result<MmapVec> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let size = ((HEADER_WORDS + len) * 8) as u64;
+        let sized = file.metadata()?.len() == size;
+        if !sized {
+            file.set_len(0)?;
+            file.set_len(size)?;
+        }
+        // the file must not change under the mapping, nothing else writes it
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        let words = as_words_mut(&mut map);
+        let header = [w as u64, h as u64];
+        if !sized || words[..HEADER_WORDS] != header {
+            words.fill(0);
+            words[..HEADER_WORDS].copy_from_slice(&header);
+        }
+        Ok(MmapVec::Mapped(map))
+    }
+    pub fn is_mapped(&self) -> bool {
+        match self {
+            MmapVec::Heap(_) => false,
+            #[cfg(feature = "mmap-state")]
+            MmapVec::Mapped(_) => true,
+        }
+    }
+}
+
+impl From<Vec<u64>> for MmapVec {
+    fn from(v: Vec<u64>) -> MmapVec {
+        MmapVec::Heap(v)
+    }
+}
+
+// a copy of a mapped field lives on the heap
+impl Clone for MmapVec {
+    fn clone(&self) -> MmapVec {
+        MmapVec::Heap(self.to_vec())
+    }
+}
+
+impl std::ops::Deref for MmapVec {
+    type Target = [u64];
+    fn deref(&self) -> &[u64] {
+        match self {
+            MmapVec::Heap(v) => v,
+            #[cfg(feature = "mmap-state")]
+            MmapVec::Mapped(map) => &as_words(map)[HEADER_WORDS..],
+        }
+    }
+}
+
+impl std::ops::DerefMut for MmapVec {
+    fn deref_mut(&mut self) -> &mut [u64] {
+        match self {
+            MmapVec::Heap(v) => v,
+            #[cfg(feature = "mmap-state")]
+            MmapVec::Mapped(map) => &mut as_words_mut(map)[HEADER_WORDS..],
+        }
+    }
+}
@@ -0,0 +1,80 @@
+// gradient noise with Perlin's reference permutation, the same pattern every
+// run, callers offset the coordinates for variety
+const PERM: [u8; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225, 140, 36, 103, 30, 69,
+    142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219,
+    203, 117, 35, 11, 32, 57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230,
+    220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80, 73, 209, 76,
+    132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173,
+    186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212, 207, 206,
+    59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163,
+    70, 221, 153, 101, 155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
+    178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162,
+    241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157, 184, 84, 204,
+    176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93, 222, 114, 67, 29, 24, 72, 243, 141,
+    128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+fn hash(x: i32, y: i32) -> u8 {
+    let a = PERM[(x & 255) as usize];
+    PERM[((a as i32 + y) & 255) as usize]
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// dot product of (dx, dy) with one of eight gradients picked by h
+fn grad(h: u8, dx: f32, dy: f32) -> f32 {
+    match h & 7 {
+        0 => dx + dy,
+        1 => dx - dy,
+        2 => -dx + dy,
+        3 => -dx - dy,
+        4 => dx,
+        5 => -dx,
+        6 => dy,
+        _ => -dy,
+    }
+}
+
+// roughly in -1..1, 0 at every integer point
+fn perlin2(x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (dx, dy) = (x - x0, y - y0);
+    let (ix, iy) = (x0 as i32, y0 as i32);
+    let (u, v) = (fade(dx), fade(dy));
+    let top = lerp(
+        grad(hash(ix, iy), dx, dy),
+        grad(hash(ix + 1, iy), dx - 1., dy),
+        u,
+    );
+    let bottom = lerp(
+        grad(hash(ix, iy + 1), dx, dy - 1.),
+        grad(hash(ix + 1, iy + 1), dx - 1., dy - 1.),
+        u,
+    );
+    lerp(top, bottom, v)
+}
+
+// octaves of noise at doubling frequencies, each persistence times as strong as
+// the last, scaled into 0..1 with 0.5 on average
+pub fn fbm2(x: f32, y: f32, freq: f32, octaves: usize, persistence: f32) -> f32 {
+    let (mut sum, mut norm) = (0., 0.);
+    let (mut f, mut amp) = (freq, 1.);
+    for _ in 0..octaves {
+        sum += amp * perlin2(x * f, y * f);
+        norm += amp;
+        f *= 2.;
+        amp *= persistence;
+    }
+    match norm > 0. {
+        false => 0.5,
+        true => (0.5 + 0.5 * sum / norm).clamp(0., 1.),
+    }
+}
@@ -0,0 +1,386 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+#[cfg(feature = "recording")]
+use gif::{Encoder, EncodingError, Frame, Repeat};
+use macroquad::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::{CellField, CharField, Fire, SimStats};
+
+// uniform 3-3-2 palette, index rrrgggbb
+#[cfg(feature = "recording")]
+fn palette() -> Vec<u8> {
+    (0..=255u32)
+        .flat_map(|i| {
+            [
+                (i >> 5) * 255 / 7,
+                (i >> 2 & 7) * 255 / 7,
+                (i & 3) * 255 / 3,
+            ]
+        })
+        .map(|c| c as u8)
+        .collect()
+}
+
+#[cfg(feature = "recording")]
+pub struct GifRecorder {
+    encoder: Encoder<BufWriter<File>>,
+    frame_count: usize,
+    delay_cs: u16,
+}
+
+#[cfg(feature = "recording")]
+impl GifRecorder {
+    pub fn new(path: &str, w: u16, h: u16, fps: u16) -> Result<GifRecorder, EncodingError> {
+        let file = File::create(path)?;
+        let mut encoder = Encoder::new(BufWriter::new(file), w, h, &palette())?;
+        encoder.set_repeat(Repeat::Infinite)?;
+        Ok(GifRecorder {
+            encoder,
+            frame_count: 0,
+            delay_cs: 100 / fps.max(1),
+        })
+    }
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+    pub fn add_frame(&mut self, image: &Image) -> Result<(), EncodingError> {
+        let pixels: Vec<u8> = image
+            .bytes
+            .chunks_exact(4)
+            .map(|p| (p[0] & 0xe0) | (p[1] >> 5) << 2 | p[2] >> 6)
+            .collect();
+        let mut frame = Frame::from_indexed_pixels(image.width, image.height, pixels, None);
+        frame.delay = self.delay_cs;
+        self.encoder.write_frame(&frame)?;
+        self.frame_count += 1;
+        Ok(())
+    }
+    // writes the trailer, dropping the recorder does the same but loses errors
+    pub fn finish(self) -> Result<(), EncodingError> {
+        self.encoder.into_inner()?.flush()?;
+        Ok(())
+    }
+}
+
+// one csv row of stats every interval steps, does nothing without a file
+#[cfg(feature = "stats-csv")]
+pub struct StatsRecorder {
+    writer: Option<BufWriter<File>>,
+    interval: usize,
+}
+
+#[cfg(feature = "stats-csv")]
+impl StatsRecorder {
+    pub fn new(path: Option<&str>, interval: usize) -> StatsRecorder {
+        let writer = path.and_then(|p| {
+            File::create(p)
+                .and_then(|f| {
+                    let mut w = BufWriter::with_capacity(1024, f);
+                    writeln!(
+                        w,
+                        "frame,tree_count,fire_count,burned_this_frame,density,max_fire_age,extinguished_early"
+                    )?;
+                    Ok(w)
+                })
+                .map_err(|e| warn!("could not write {}: {}", p, e))
+                .ok()
+        });
+        StatsRecorder { writer, interval }
+    }
+    pub fn record(&mut self, frame: usize, stats: &SimStats) {
+        let Some(w) = &mut self.writer else {
+            return;
+        };
+        if !frame.is_multiple_of(self.interval) {
+            return;
+        }
+        let row = writeln!(
+            w,
+            "{},{},{},{},{:.6},{},{}",
+            frame,
+            stats.tree_count,
+            stats.fire_count,
+            stats.burned_last,
+            stats.density,
+            stats.max_fire_age,
+            stats.extinguished_last
+        );
+        if let Err(e) = row {
+            warn!("could not write stats: {}", e);
+            self.writer = None;
+        }
+    }
+    // exit() skips destructors, so the buffer is flushed here
+    pub fn finish(&mut self) {
+        if let Some(Err(e)) = self.writer.take().map(|mut w| w.flush()) {
+            warn!("could not write stats: {}", e);
+        }
+    }
+}
+
+// bytes before the cells of a .npy file, the header is padded to this so
+// finalize can rewrite the shape in place
+const NPY_HEADER_LEN: usize = 128;
+
+fn npy_header(frames: usize, h: usize, w: usize) -> Vec<u8> {
+    let dict = format!(
+        "{{'descr': '|u1', 'fortran_order': False, 'shape': ({}, {}, {}), }}",
+        frames, h, w
+    );
+    let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+    bytes.extend(((NPY_HEADER_LEN - 10) as u16).to_le_bytes());
+    bytes.extend(dict.bytes());
+    bytes.resize(NPY_HEADER_LEN - 1, b' ');
+    bytes.push(b'\n');
+    bytes
+}
+
+// a uint8 array of shape (frames, h, w) in the numpy .npy format, one frame
+// per step with 0 empty, 1 tree, 2 burning and 3 charred
+pub struct NpyWriter {
+    file: BufWriter<File>,
+    w: usize,
+    h: usize,
+    frame_count: usize,
+}
+
+impl NpyWriter {
+    pub fn new(path: &str, w: usize, h: usize) -> std::io::Result<NpyWriter> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&npy_header(0, h, w))?;
+        Ok(NpyWriter {
+            file,
+            w,
+            h,
+            frame_count: 0,
+        })
+    }
+    pub fn append_frame(
+        &mut self,
+        cf: &CellField,
+        charred: &CharField,
+        fires: &[Fire],
+    ) -> std::io::Result<()> {
+        // every frame has the size the header was written for
+        if cf.w != self.w || cf.h != self.h {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the grid changed size",
+            ));
+        }
+        let mut cells = vec![0u8; self.w * self.h];
+        for y in 0..self.h {
+            for x in 0..self.w {
+                cells[y * self.w + x] = match (cf.get(x, y), charred.get(x, y) != 0) {
+                    (true, _) => 1,
+                    (false, true) => 3,
+                    (false, false) => 0,
+                };
+            }
+        }
+        for f in fires {
+            cells[f.y * self.w + f.x] = 2;
+        }
+        self.file.write_all(&cells)?;
+        self.frame_count += 1;
+        Ok(())
+    }
+    // the header says no frames until this writes the count, exit() skips
+    // destructors so it has to be called before
+    pub fn finalize(mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        let file = self.file.get_mut();
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&npy_header(self.frame_count, self.h, self.w))?;
+        file.flush()
+    }
+}
+
+// stand-ins for builds without the recording and stats-csv features
+#[cfg(not(feature = "recording"))]
+pub struct GifRecorder;
+
+#[cfg(not(feature = "recording"))]
+impl GifRecorder {
+    pub fn new(_path: &str, _w: u16, _h: u16, _fps: u16) -> Result<GifRecorder, &'static str> {
+        Err("built without the recording feature")
+    }
+    pub fn frame_count(&self) -> usize {
+        0
+    }
+    pub fn add_frame(&mut self, _image: &Image) -> Result<(), &'static str> {
+        Ok(())
+    }
+    pub fn finish(self) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "stats-csv"))]
+pub struct StatsRecorder;
+
+#[cfg(not(feature = "stats-csv"))]
+impl StatsRecorder {
+    pub fn new(path: Option<&str>, _interval: usize) -> StatsRecorder {
+        if path.is_some() {
+            warn!("built without the stats-csv feature, no stats are written");
+        }
+        StatsRecorder
+    }
+    pub fn record(&mut self, _frame: usize, _stats: &SimStats) {}
+    pub fn finish(&mut self) {}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_png(path: &str, rgba: &[u8], w: u32, h: u32) -> Result<(), String> {
+    let file = std::fs::File::create(path).map_err(|e| e.to_string())?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), w, h);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(rgba).map_err(|e| e.to_string())
+}
+
+type PngJob = (Vec<u8>, u32, u32, String);
+
+// pngs are encoded and written on a worker thread so recording does not stall
+// the frame, the web build has no threads and saves right away
+pub struct PngSaver {
+    #[cfg(not(target_arch = "wasm32"))]
+    tx: Option<std::sync::mpsc::Sender<PngJob>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    worker: Option<std::thread::JoinHandle<()>>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl PngSaver {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new() -> PngSaver {
+        let (tx, rx) = std::sync::mpsc::channel::<PngJob>();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let left = pending.clone();
+        let worker = std::thread::spawn(move || {
+            while let Ok((rgba, w, h, path)) = rx.recv() {
+                if let Err(e) = write_png(&path, &rgba, w, h) {
+                    warn!("could not write {}: {}", path, e);
+                }
+                left.fetch_sub(1, Ordering::Relaxed);
+            }
+        });
+        PngSaver {
+            tx: Some(tx),
+            worker: Some(worker),
+            pending,
+        }
+    }
+    #[cfg(target_arch = "wasm32")]
+    pub fn new() -> PngSaver {
+        PngSaver {
+            pending: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+    // copies the pixels, the image can change right after
+    pub fn save(&self, image: &Image, path: String) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(tx) = &self.tx {
+            self.pending.fetch_add(1, Ordering::Relaxed);
+            let job = (
+                image.bytes.clone(),
+                image.width as u32,
+                image.height as u32,
+                path,
+            );
+            if tx.send(job).is_err() {
+                self.pending.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        image.export_png(&path);
+    }
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+    // waits for the queued pngs, exit() would drop them
+    pub fn finish(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.tx = None;
+            if let Some(Err(_)) = self.worker.take().map(|w| w.join()) {
+                warn!("the png worker panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "recording")]
+    #[test]
+    fn gif_has_magic_bytes() {
+        let path = std::env::temp_dir().join("forestfire_test.gif");
+        let path = path.to_str().unwrap();
+        let mut gif = GifRecorder::new(path, 16, 12, 30).unwrap();
+        let mut image = Image::gen_image_color(16, 12, BLACK);
+        for i in 0..100 {
+            image.set_pixel(i % 16, i / 16 % 12, ORANGE);
+            gif.add_frame(&image).unwrap();
+        }
+        assert_eq!(gif.frame_count(), 100);
+        gif.finish().unwrap();
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(&bytes[..6], b"GIF89a");
+        assert_eq!(bytes.last(), Some(&0x3b));
+    }
+
+    #[cfg(feature = "stats-csv")]
+    #[test]
+    fn stats_csv_has_a_row_per_frame() {
+        let path = std::env::temp_dir().join("forestfire_test.csv");
+        let path = path.to_str().unwrap();
+        let mut csv = StatsRecorder::new(Some(path), 1);
+        let mut stats = SimStats::new();
+        for frame in 0..100 {
+            stats.tree_count = frame * 3;
+            csv.record(frame, &stats);
+        }
+        csv.finish();
+        let text = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 101);
+        assert!(lines[0].starts_with("frame,tree_count,"));
+        assert!(lines[100].starts_with("99,297,"));
+    }
+
+    #[test]
+    fn npy_header_has_shape() {
+        let path = std::env::temp_dir().join("forestfire_test.npy");
+        let path = path.to_str().unwrap();
+        let (w, h) = (12, 9);
+        let mut npy = NpyWriter::new(path, w, h).unwrap();
+        let mut cf = CellField::new(w, h);
+        let mut charred = CharField::new(w, h);
+        cf.set(1, 0);
+        charred.mark(2, 0, 1);
+        let fires = [Fire::new(3, 0, 1.0)];
+        for _ in 0..10 {
+            npy.append_frame(&cf, &charred, &fires).unwrap();
+        }
+        npy.finalize().unwrap();
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(bytes.len(), NPY_HEADER_LEN + 10 * w * h);
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let header = std::str::from_utf8(&bytes[10..NPY_HEADER_LEN]).unwrap();
+        assert!(header.contains("'shape': (10, 9, 12)"), "{}", header);
+        assert!(header.ends_with('\n'));
+        let frame = &bytes[NPY_HEADER_LEN + 9 * w * h..];
+        assert_eq!(&frame[..5], &[0, 1, 3, 2, 0]);
+    }
+}
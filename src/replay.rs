@@ -0,0 +1,111 @@
+use std::collections::HashSet;
+
+use crate::Fire;
+
+const MAX_DIFFS: usize = 2000;
+
+// fires that appeared, with their age, and fires that went out over one step
+pub struct FrameDiff {
+    pub added: Vec<(u16, u16, u16)>,
+    pub removed: Vec<(u16, u16)>,
+}
+
+impl FrameDiff {
+    pub fn between(before: &[Fire], after: &[Fire]) -> FrameDiff {
+        let old: HashSet<(usize, usize)> = before.iter().map(|f| (f.x, f.y)).collect();
+        let new: HashSet<(usize, usize)> = after.iter().map(|f| (f.x, f.y)).collect();
+        FrameDiff {
+            added: after
+                .iter()
+                .filter(|f| !old.contains(&(f.x, f.y)))
+                .map(|f| (f.x as u16, f.y as u16, f.age.min(u16::MAX as usize) as u16))
+                .collect(),
+            removed: old
+                .difference(&new)
+                .map(|&(x, y)| (x as u16, y as u16))
+                .collect(),
+        }
+    }
+}
+
+fn remove_at(fires: &mut Vec<Fire>, cells: impl Iterator<Item = (u16, u16)>) {
+    let cells: HashSet<(u16, u16)> = cells.collect();
+    fires.retain(|f| !cells.contains(&(f.x as u16, f.y as u16)));
+}
+
+// ages are not replayed, fires keep the age they appeared with
+pub fn apply_diff(fires: &mut Vec<Fire>, diff: &FrameDiff) {
+    remove_at(fires, diff.removed.iter().copied());
+    for &(x, y, age) in &diff.added {
+        let mut f = Fire::new(x as usize, y as usize, 1.0);
+        f.age = age as usize;
+        fires.push(f);
+    }
+}
+
+pub fn reverse_diff(fires: &mut Vec<Fire>, diff: &FrameDiff) {
+    remove_at(fires, diff.added.iter().map(|&(x, y, _)| (x, y)));
+    for &(x, y) in &diff.removed {
+        fires.push(Fire::new(x as usize, y as usize, 1.0));
+    }
+}
+
+pub struct ReplayMode {
+    pub recording: bool,
+    pub buffer: Vec<FrameDiff>,
+    // diffs applied to the fires on screen, buffer.len() at the live end
+    pub cursor: usize,
+    // fires after the newest diff, where playback starts
+    pub last: Vec<Fire>,
+}
+
+impl ReplayMode {
+    pub fn new() -> ReplayMode {
+        ReplayMode {
+            recording: false,
+            buffer: Vec::new(),
+            cursor: 0,
+            last: Vec::new(),
+        }
+    }
+    pub fn start(&mut self, fires: &[Fire]) {
+        self.recording = true;
+        self.buffer.clear();
+        self.cursor = 0;
+        self.last = fires.to_vec();
+    }
+    // called after every step while recording
+    pub fn record(&mut self, fires: &[Fire]) {
+        self.push(FrameDiff::between(&self.last, fires));
+        self.last = fires.to_vec();
+    }
+    fn push(&mut self, diff: FrameDiff) {
+        if self.buffer.len() == MAX_DIFFS {
+            self.buffer.remove(0);
+        }
+        self.buffer.push(diff);
+        self.cursor = self.buffer.len();
+    }
+    // the step* functions return whether the fires changed
+    pub fn step_back(&mut self, fires: &mut Vec<Fire>) -> bool {
+        if self.cursor == 0 {
+            return false;
+        }
+        self.cursor -= 1;
+        reverse_diff(fires, &self.buffer[self.cursor]);
+        true
+    }
+    pub fn step_forward(&mut self, fires: &mut Vec<Fire>) -> bool {
+        if self.cursor == self.buffer.len() {
+            return false;
+        }
+        apply_diff(fires, &self.buffer[self.cursor]);
+        self.cursor += 1;
+        true
+    }
+    pub fn restart(&mut self, fires: &mut Vec<Fire>) -> bool {
+        let moved = self.cursor > 0;
+        while self.step_back(fires) {}
+        moved
+    }
+}
@@ -0,0 +1,214 @@
+use macroquad::prelude::*;
+
+#[cfg(feature = "gpu-shader")]
+use crate::GridType;
+use crate::{CellField, Fire, SimConfig};
+
+#[cfg(feature = "gpu-shader")]
+const VERTEX: &str = r#"#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+
+varying vec2 uv;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    uv = texcoord;
+}"#;
+
+// trees holds the raw CellField words, two texels per u64: texel 2*word + iy/4,
+// channel iy%4, bit ix for cell (8*tx + ix, 8*ty + iy). fires holds age + 1
+// (0 = not burning) and intensity per cell, two cells per texel.
+#[cfg(feature = "gpu-shader")]
+const FRAGMENT: &str = r#"#version 100
+#ifdef GL_FRAGMENT_PRECISION_HIGH
+precision highp float;
+#else
+precision mediump float;
+#endif
+
+varying vec2 uv;
+
+uniform sampler2D trees;
+uniform sampler2D fires;
+uniform vec2 size;
+uniform vec2 span;
+uniform float hex;
+uniform vec2 treesize;
+uniform vec2 firesize;
+uniform float firemaxage;
+uniform float colorphase;
+
+float channel(vec4 t, float c) {
+    return dot(t, vec4(equal(vec4(c), vec4(0.0, 1.0, 2.0, 3.0))));
+}
+
+void main() {
+    // odd rows of a hexagonal grid sit half a cell to the right, span is the
+    // drawn width in cells with that half cell
+    vec2 q = uv * span;
+    q.x -= hex * 0.5 * mod(floor(q.y), 2.0);
+    vec2 p = floor(q);
+    if (p.x < 0.0 || p.x >= size.x) {
+        discard;
+    }
+
+    float fx = floor(p.x / 2.0);
+    vec4 ft = texture2D(fires, (vec2(fx, p.y) + 0.5) / firesize);
+    float c = 2.0 * (p.x - 2.0 * fx);
+    float age1 = floor(channel(ft, c) * 255.0 + 0.5);
+    if (age1 > 0.0) {
+        float intensity = channel(ft, c + 1.0);
+        gl_FragColor = vec4(intensity, (age1 - 1.0) / firemaxage * intensity, 0.0, 1.0);
+        return;
+    }
+
+    vec2 tile = floor(p / 8.0);
+    vec2 i = p - 8.0 * tile;
+    float hi = floor(i.y / 4.0);
+    vec4 tt = texture2D(trees, (vec2(2.0 * tile.x + hi, tile.y) + 0.5) / treesize);
+    float bits = floor(channel(tt, i.y - 4.0 * hi) * 255.0 + 0.5);
+    float tree = mod(floor(bits / pow(2.0, i.x)), 2.0);
+
+    gl_FragColor = vec4(0.0, tree * abs(cos(colorphase)), tree * abs(sin(colorphase)), 1.0);
+}"#;
+
+// renders straight from the cell bits and fire ages instead of the full RGBA image;
+// tree color follows the current colorphase rather than the one at planting time
+#[cfg(feature = "gpu-shader")]
+pub struct ShaderRenderer {
+    material: Material,
+    trees: Texture2D,
+    fires: Texture2D,
+    treebuf: Image,
+    firebuf: Image,
+    w: usize,
+    h: usize,
+}
+
+#[cfg(feature = "gpu-shader")]
+fn blank(w: usize, h: usize) -> Image {
+    Image {
+        bytes: vec![0; w * h * 4],
+        width: w as u16,
+        height: h as u16,
+    }
+}
+
+#[cfg(feature = "gpu-shader")]
+fn nearest_texture(image: &Image) -> Texture2D {
+    let texture = Texture2D::from_image(image);
+    texture.set_filter(FilterMode::Nearest);
+    texture
+}
+
+#[cfg(feature = "gpu-shader")]
+impl ShaderRenderer {
+    pub fn new(w: usize, h: usize) -> Option<ShaderRenderer> {
+        let material = load_material(
+            VERTEX,
+            FRAGMENT,
+            MaterialParams {
+                uniforms: vec![
+                    ("size".to_string(), miniquad::UniformType::Float2),
+                    ("span".to_string(), miniquad::UniformType::Float2),
+                    ("hex".to_string(), miniquad::UniformType::Float1),
+                    ("treesize".to_string(), miniquad::UniformType::Float2),
+                    ("firesize".to_string(), miniquad::UniformType::Float2),
+                    ("firemaxage".to_string(), miniquad::UniformType::Float1),
+                    ("colorphase".to_string(), miniquad::UniformType::Float1),
+                ],
+                textures: vec!["trees".to_string(), "fires".to_string()],
+                ..Default::default()
+            },
+        )
+        .ok()?;
+
+        let treebuf = blank(w.div_ceil(8) * 2, h.div_ceil(8));
+        let firebuf = blank(w.div_ceil(2), h);
+        Some(ShaderRenderer {
+            material,
+            trees: nearest_texture(&treebuf),
+            fires: nearest_texture(&firebuf),
+            treebuf,
+            firebuf,
+            w,
+            h,
+        })
+    }
+    pub fn resize(&mut self, w: usize, h: usize) {
+        self.trees.delete();
+        self.fires.delete();
+        self.treebuf = blank(w.div_ceil(8) * 2, h.div_ceil(8));
+        self.firebuf = blank(w.div_ceil(2), h);
+        self.trees = nearest_texture(&self.treebuf);
+        self.fires = nearest_texture(&self.firebuf);
+        self.w = w;
+        self.h = h;
+    }
+    pub fn update(&mut self, cf: &CellField, fires: &[Fire]) {
+        for (dst, word) in self.treebuf.bytes.chunks_exact_mut(8).zip(cf.arr.iter()) {
+            dst.copy_from_slice(&word.to_le_bytes());
+        }
+        self.trees.update(&self.treebuf);
+
+        let stride = self.firebuf.width as usize * 4;
+        self.firebuf.bytes.fill(0);
+        for f in fires {
+            let i = f.y * stride + 2 * f.x;
+            self.firebuf.bytes[i] = (f.age + 1).min(255) as u8;
+            self.firebuf.bytes[i + 1] = (f.intensity * 255.) as u8;
+        }
+        self.fires.update(&self.firebuf);
+    }
+    pub fn draw(&self, config: &SimConfig, colorphase: f32, dest: Rect) {
+        let m = self.material;
+        m.set_texture("trees", self.trees);
+        m.set_texture("fires", self.fires);
+        let hex = match config.grid_type {
+            GridType::Square => 0.,
+            GridType::Hexagonal => 1.,
+        };
+        let span = vec2(self.w as f32 + 0.5 * hex, self.h as f32);
+        m.set_uniform("size", vec2(self.w as f32, self.h as f32));
+        m.set_uniform("span", span);
+        m.set_uniform("hex", hex);
+        m.set_uniform(
+            "treesize",
+            vec2(self.treebuf.width as f32, self.treebuf.height as f32),
+        );
+        m.set_uniform(
+            "firesize",
+            vec2(self.firebuf.width as f32, self.firebuf.height as f32),
+        );
+        m.set_uniform("firemaxage", config.firemaxage);
+        m.set_uniform("colorphase", colorphase);
+
+        gl_use_material(m);
+        draw_rectangle(
+            dest.x,
+            dest.y,
+            dest.w * span.x / self.w as f32,
+            dest.h,
+            WHITE,
+        );
+        gl_use_default_material();
+    }
+}
+
+// without gpu-shader there is never a renderer and the CPU image is drawn
+#[cfg(not(feature = "gpu-shader"))]
+pub struct ShaderRenderer;
+
+#[cfg(not(feature = "gpu-shader"))]
+impl ShaderRenderer {
+    pub fn new(_w: usize, _h: usize) -> Option<ShaderRenderer> {
+        None
+    }
+    pub fn resize(&mut self, _w: usize, _h: usize) {}
+    pub fn update(&mut self, _cf: &CellField, _fires: &[Fire]) {}
+    pub fn draw(&self, _config: &SimConfig, _colorphase: f32, _dest: Rect) {}
+}
@@ -0,0 +1,590 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::{CellField, Fire, SimConfig, SparseCellField};
+
+#[cfg(not(target_arch = "wasm32"))]
+const STATE_FILE: &str = "state.json";
+#[cfg(not(target_arch = "wasm32"))]
+const EVENTS_FILE: &str = "events.json";
+#[cfg(not(target_arch = "wasm32"))]
+const EVENTS_CSV_FILE: &str = "events.csv";
+#[cfg(not(target_arch = "wasm32"))]
+pub const FIRES_FILE: &str = "fires.bin";
+#[cfg(not(target_arch = "wasm32"))]
+const CONFIG_FILE: &str = "forestfire.toml";
+
+// CellField as stored on disk, the words little-endian and base64 encoded
+#[derive(Serialize, Deserialize)]
+pub struct CellFieldData {
+    w: usize,
+    h: usize,
+    arr: String,
+}
+
+impl From<CellField> for CellFieldData {
+    fn from(cf: CellField) -> CellFieldData {
+        let bytes: Vec<u8> = cf.arr.iter().flat_map(|w| w.to_le_bytes()).collect();
+        CellFieldData {
+            w: cf.w,
+            h: cf.h,
+            arr: STANDARD.encode(bytes),
+        }
+    }
+}
+
+impl TryFrom<CellFieldData> for CellField {
+    type Error = String;
+
+    fn try_from(data: CellFieldData) -> Result<CellField, String> {
+        let bytes = STANDARD.decode(&data.arr).map_err(|e| e.to_string())?;
+        let mut cf = CellField::new(data.w, data.h);
+        if bytes.len() != cf.arr.len() * 8 {
+            return Err(format!(
+                "{} bytes of cells for a {}x{} field",
+                bytes.len(),
+                data.w,
+                data.h
+            ));
+        }
+        for (w, b) in cf.arr.iter_mut().zip(bytes.chunks_exact(8)) {
+            *w = u64::from_le_bytes(b.try_into().unwrap());
+        }
+        Ok(cf)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub config: SimConfig,
+    pub cellfield: CellField,
+    pub wet_field: CellField,
+    pub fires: Vec<Fire>,
+    pub frno: usize,
+    pub colorphase: f32,
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("state is always serializable")
+    }
+    pub fn from_json(s: &str) -> Result<Snapshot, serde_json::Error> {
+        serde_json::from_str(s)
+    }
+}
+
+// little-endian u32 count, then x, y and age as u16 for each fire. Intensity is
+// not kept, loaded fires burn at 1.0, and ages above 65535 are stored as 65535
+fn encode_fires(fires: &[Fire]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(4 + fires.len() * 6);
+    bytes.extend((fires.len() as u32).to_le_bytes());
+    for f in fires {
+        for v in [f.x, f.y, f.age] {
+            bytes.extend((v.min(u16::MAX as usize) as u16).to_le_bytes());
+        }
+    }
+    bytes
+}
+
+// fires outside w x h are skipped with one warning
+fn decode_fires(bytes: &[u8], w: usize, h: usize) -> Result<Vec<Fire>, String> {
+    let (count, rest) = bytes.split_first_chunk::<4>().ok_or("no fire count")?;
+    let n = u32::from_le_bytes(*count) as usize;
+    if rest.len() != n * 6 {
+        return Err(format!("{} bytes for {} fires", rest.len(), n));
+    }
+    let mut fires = Vec::with_capacity(n);
+    for c in rest.chunks_exact(6) {
+        let v = |i: usize| u16::from_le_bytes([c[i], c[i + 1]]) as usize;
+        let (x, y) = (v(0), v(2));
+        if x < w && y < h {
+            let mut f = Fire::new(x, y, 1.0);
+            f.age = v(4);
+            fires.push(f);
+        }
+    }
+    if fires.len() < n {
+        macroquad::logging::warn!(
+            "skipped {} fires outside the {}x{} grid",
+            n - fires.len(),
+            w,
+            h
+        );
+    }
+    Ok(fires)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_fires(fires: &[Fire], path: &str) -> Result<(), String> {
+    std::fs::write(path, encode_fires(fires)).map_err(|e| e.to_string())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_fires(path: &str, w: usize, h: usize) -> Result<Vec<Fire>, String> {
+    decode_fires(&std::fs::read(path).map_err(|e| e.to_string())?, w, h)
+}
+
+// the browser keeps a single set of fires, base64 encoded
+#[cfg(target_arch = "wasm32")]
+pub const FIRES_FILE: &str = "fires";
+
+#[cfg(target_arch = "wasm32")]
+pub fn save_fires(fires: &[Fire], key: &str) -> Result<(), String> {
+    let data = STANDARD.encode(encode_fires(fires));
+    quad_storage::STORAGE.lock().unwrap().set(key, &data);
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load_fires(key: &str, w: usize, h: usize) -> Result<Vec<Fire>, String> {
+    let data = quad_storage::STORAGE
+        .lock()
+        .unwrap()
+        .get(key)
+        .ok_or("no saved fires")?;
+    decode_fires(&STANDARD.decode(data).map_err(|e| e.to_string())?, w, h)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_state(json: &str) {
+    if let Err(e) = std::fs::write(STATE_FILE, json) {
+        macroquad::logging::warn!("could not write {}: {}", STATE_FILE, e);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_state() -> Option<String> {
+    std::fs::read_to_string(STATE_FILE)
+        .map_err(|e| macroquad::logging::warn!("could not read {}: {}", STATE_FILE, e))
+        .ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_events(json: &str) {
+    if let Err(e) = std::fs::write(EVENTS_FILE, json) {
+        macroquad::logging::warn!("could not write {}: {}", EVENTS_FILE, e);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_events_csv(csv: &str) {
+    if let Err(e) = std::fs::write(EVENTS_CSV_FILE, csv) {
+        macroquad::logging::warn!("could not write {}: {}", EVENTS_CSV_FILE, e);
+    }
+}
+
+// same contents as write_state, named by step
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_snapshot(dir: &str, frno: usize, json: &str) {
+    let path = std::path::Path::new(dir).join(format!("snapshot_{:07}.json", frno));
+    if let Err(e) = std::fs::write(&path, json) {
+        macroquad::logging::warn!("could not write {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write_state(json: &str) {
+    quad_storage::STORAGE.lock().unwrap().set("state", json);
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read_state() -> Option<String> {
+    quad_storage::STORAGE.lock().unwrap().get("state")
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write_events(json: &str) {
+    quad_storage::STORAGE.lock().unwrap().set("events", json);
+}
+
+// the browser only keeps the latest one
+#[cfg(target_arch = "wasm32")]
+pub fn write_snapshot(_dir: &str, _frno: usize, json: &str) {
+    quad_storage::STORAGE.lock().unwrap().set("snapshot", json);
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write_events_csv(_csv: &str) {
+    macroquad::logging::info!("csv export is not available on the web");
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_config() -> Option<SimConfig> {
+    if !std::path::Path::new(CONFIG_FILE).exists() {
+        return None;
+    }
+    SimConfig::from_toml_file(CONFIG_FILE)
+        .map_err(|e| macroquad::logging::warn!("could not load {}: {}", CONFIG_FILE, e))
+        .ok()
+}
+
+#[derive(Deserialize, Serialize)]
+struct NamedPreset {
+    name: String,
+    #[serde(flatten)]
+    config: SimConfig,
+}
+
+// forestfire.toml, the settings at the top and any number of [[presets]]
+#[derive(Deserialize, Serialize)]
+struct ConfigFile {
+    #[serde(flatten)]
+    config: SimConfig,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    presets: Vec<NamedPreset>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_config_file() -> Option<ConfigFile> {
+    let text = std::fs::read_to_string(CONFIG_FILE).ok()?;
+    toml::from_str(&text)
+        .map_err(|e| macroquad::logging::warn!("could not load {}: {}", CONFIG_FILE, e))
+        .ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_presets() -> Vec<(String, SimConfig)> {
+    read_config_file()
+        .map(|f| f.presets.into_iter().map(|p| (p.name, p.config)).collect())
+        .unwrap_or_default()
+}
+
+// presets already in the file are kept
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_config(config: &SimConfig) {
+    let file = ConfigFile {
+        config: config.clone(),
+        presets: read_config_file().map(|f| f.presets).unwrap_or_default(),
+    };
+    let written = toml::to_string_pretty(&file)
+        .map_err(|e| e.to_string())
+        .and_then(|text| std::fs::write(CONFIG_FILE, text).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        macroquad::logging::warn!("could not write {}: {}", CONFIG_FILE, e);
+    }
+}
+
+// the controls as they were left, in the user's data directory so every
+// working directory shares them
+#[cfg(not(target_arch = "wasm32"))]
+fn prefs_path() -> Option<std::path::PathBuf> {
+    let data = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => std::path::PathBuf::from(std::env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(data.join("forestfire").join("prefs.json"))
+}
+
+// None when nothing was saved yet, it can not be read or forestfire.toml was
+// changed since, so editing the file still takes effect
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_prefs() -> Option<SimConfig> {
+    let path = prefs_path()?;
+    let modified = |p: &std::path::Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    if let (Some(config), Some(prefs)) = (modified(CONFIG_FILE.as_ref()), modified(&path)) {
+        if config > prefs {
+            return None;
+        }
+    }
+    let text = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&text)
+        .map_err(|e| macroquad::logging::warn!("could not load the saved controls: {}", e))
+        .ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_prefs(config: &SimConfig) {
+    let Some(path) = prefs_path() else {
+        return;
+    };
+    let written = serde_json::to_string_pretty(config)
+        .map_err(|e| e.to_string())
+        .and_then(|json| {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+            }
+            std::fs::write(&path, json).map_err(|e| e.to_string())
+        });
+    if let Err(e) = written {
+        macroquad::logging::warn!("could not write {}: {}", path.display(), e);
+    }
+}
+
+// an empty file next to the prefs once the tutorial ran to its end
+#[cfg(not(target_arch = "wasm32"))]
+pub fn tutorial_done() -> bool {
+    prefs_path().is_some_and(|p| p.with_file_name("tutorial_done").exists())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn mark_tutorial_done() {
+    let Some(path) = prefs_path().map(|p| p.with_file_name("tutorial_done")) else {
+        return;
+    };
+    let written = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&path, ""));
+    if let Err(e) = written {
+        macroquad::logging::warn!("could not write {}: {}", path.display(), e);
+    }
+}
+
+// raw little-endian f32 matrix, row-major
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_heatmap(counts: &[u32], w: usize, h: usize) {
+    let path = format!("heatmap_{}x{}.f32", w, h);
+    let bytes: Vec<u8> = counts
+        .iter()
+        .flat_map(|&n| (n as f32).to_le_bytes())
+        .collect();
+    if let Err(e) = std::fs::write(&path, bytes) {
+        macroquad::logging::warn!("could not write {}: {}", path, e);
+    }
+}
+
+// the fire return interval of every cell, after a w and h u32 header
+#[cfg(not(target_arch = "wasm32"))]
+pub fn export_fri(counts: &[u32], n_history: usize, w: usize, h: usize, path: &str) {
+    let mut bytes = Vec::with_capacity(8 + counts.len() * 4);
+    bytes.extend((w as u32).to_le_bytes());
+    bytes.extend((h as u32).to_le_bytes());
+    for &n in counts {
+        bytes.extend(crate::fire_return_interval(n, n_history).to_le_bytes());
+    }
+    if let Err(e) = std::fs::write(path, bytes) {
+        macroquad::logging::warn!("could not write {}: {}", path, e);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_svg(svg: &str, frno: usize) {
+    let path = format!("state_{:05}.svg", frno);
+    if let Err(e) = std::fs::write(&path, svg) {
+        macroquad::logging::warn!("could not write {}: {}", path, e);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write_svg(_svg: &str, _frno: usize) {
+    macroquad::logging::info!("svg export is not available on the web");
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn export_fri(_counts: &[u32], _n_history: usize, _w: usize, _h: usize, _path: &str) {
+    macroquad::logging::info!("fri export is not available on the web");
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write_heatmap(_counts: &[u32], _w: usize, _h: usize) {
+    macroquad::logging::info!("heatmap export is not available on the web");
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read_config() -> Option<SimConfig> {
+    None
+}
+
+// the browser keeps them in local storage
+#[cfg(target_arch = "wasm32")]
+pub fn load_prefs() -> Option<SimConfig> {
+    let json = quad_storage::STORAGE.lock().unwrap().get("prefs")?;
+    serde_json::from_str(&json).ok()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn tutorial_done() -> bool {
+    quad_storage::STORAGE
+        .lock()
+        .unwrap()
+        .get("tutorial_done")
+        .is_some()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn mark_tutorial_done() {
+    quad_storage::STORAGE
+        .lock()
+        .unwrap()
+        .set("tutorial_done", "1");
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save_prefs(config: &SimConfig) {
+    match serde_json::to_string(config) {
+        Ok(json) => quad_storage::STORAGE.lock().unwrap().set("prefs", &json),
+        Err(e) => macroquad::logging::warn!("could not save the controls: {}", e),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read_presets() -> Vec<(String, SimConfig)> {
+    Vec::new()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write_config(_config: &SimConfig) {
+    macroquad::logging::info!("config files are not available on the web");
+}
+
+// 8 bit RGB and RGBA pngs scaled to w x h by nearest neighbor, as rgb in 0..1
+#[cfg(not(target_arch = "wasm32"))]
+fn read_png_scaled(path: &str, w: usize, h: usize) -> Result<Vec<[f32; 3]>, String> {
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut reader = png::Decoder::new(file)
+        .read_info()
+        .map_err(|e| e.to_string())?;
+    let channels = match (reader.info().color_type, reader.info().bit_depth) {
+        (png::ColorType::Rgb, png::BitDepth::Eight) => 3,
+        (png::ColorType::Rgba, png::BitDepth::Eight) => 4,
+        (color, depth) => {
+            return Err(format!(
+                "{:?} {:?} bit pngs are not supported, only 24 and 32 bit RGB(A)",
+                color, depth as u8
+            ))
+        }
+    };
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+    let (pw, ph) = (frame.width as usize, frame.height as usize);
+
+    let mut pixels = Vec::with_capacity(w * h);
+    for y in 0..h {
+        for x in 0..w {
+            let i = (y * ph / h) * frame.line_size + (x * pw / w) * channels;
+            pixels.push([0, 1, 2].map(|c| buf[i + c] as f32 / 255.));
+        }
+    }
+    Ok(pixels)
+}
+
+// green pixels are trees, red ones fires and anything else is empty ground
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_initial_from_png(
+    path: &str,
+    w: usize,
+    h: usize,
+) -> Result<(CellField, Vec<Fire>), String> {
+    let pixels = read_png_scaled(path, w, h)?;
+    let mut cf = CellField::new(w, h);
+    let mut fires = Vec::new();
+    for (i, &[r, g, _]) in pixels.iter().enumerate() {
+        let (x, y) = (i % w, i / w);
+        if r > 0.5 {
+            fires.push(Fire::new(x, y, 1.0));
+        } else if g > 0.3 && r < 0.3 {
+            cf.set(x, y);
+        }
+    }
+    Ok((cf, fires))
+}
+
+// blue pixels are water
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_water_from_png(path: &str, w: usize, h: usize) -> Result<SparseCellField, String> {
+    let pixels = read_png_scaled(path, w, h)?;
+    let mut water = SparseCellField::new(w, h);
+    for (i, &[r, g, b]) in pixels.iter().enumerate() {
+        if b > 0.3 && r < 0.3 && g < b {
+            water.set(i % w, i / w);
+        }
+    }
+    Ok(water)
+}
+
+// the brightness of each pixel, 1 for white
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_rate_field_from_png(path: &str, w: usize, h: usize) -> Result<Vec<f32>, String> {
+    let pixels = read_png_scaled(path, w, h)?;
+    Ok(pixels
+        .iter()
+        .map(|&[r, g, b]| 0.2126 * r + 0.7152 * g + 0.0722 * b)
+        .collect())
+}
+
+// one "cx cy radius" per line, lines that do not parse are skipped with a warning
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_clusters(path: &str) -> Result<Vec<(usize, usize, usize)>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut clusters = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let nums: Result<Vec<usize>, _> = line.split_whitespace().map(str::parse).collect();
+        match nums.as_deref() {
+            Ok(&[cx, cy, r]) => clusters.push((cx, cy, r)),
+            _ => macroquad::logging::warn!("{}:{}: expected \"cx cy radius\"", path, i + 1),
+        }
+    }
+    Ok(clusters)
+}
+
+// one "x1 y1 x2 y2" segment per line, lines that do not parse are skipped with a
+// warning
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_roads(path: &str) -> Result<Vec<(usize, usize, usize, usize)>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut segs = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let nums: Result<Vec<usize>, _> = line.split_whitespace().map(str::parse).collect();
+        match nums.as_deref() {
+            Ok(&[x1, y1, x2, y2]) => segs.push((x1, y1, x2, y2)),
+            _ => macroquad::logging::warn!("{}:{}: expected \"x1 y1 x2 y2\"", path, i + 1),
+        }
+    }
+    Ok(segs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fires_round_trip() {
+        let fires: Vec<Fire> = (0..1000)
+            .map(|i| {
+                let mut f = Fire::new(i * 7 % 1920, i * 13 % 1080, 0.5);
+                f.age = i * 31 % 500;
+                f
+            })
+            .collect();
+        let bytes = encode_fires(&fires);
+        assert_eq!(bytes.len(), 4 + 1000 * 6);
+        let back = decode_fires(&bytes, 1920, 1080).unwrap();
+        assert_eq!(back.len(), fires.len());
+        for (a, b) in fires.iter().zip(&back) {
+            assert_eq!((a.x, a.y, a.age), (b.x, b.y, b.age));
+            assert_eq!(b.intensity, 1.0);
+        }
+        let mut old = Fire::new(3, 4, 1.0);
+        old.age = 70000;
+        let back = decode_fires(&encode_fires(&[old]), 10, 10).unwrap();
+        assert_eq!(back[0].age, u16::MAX as usize);
+    }
+
+    // cargo runs the tests from the crate root, next to forestfire.toml
+    #[test]
+    fn prefs_round_trip() {
+        let dir = std::env::temp_dir().join(format!("forestfire-prefs-{}", std::process::id()));
+        std::env::set_var("XDG_DATA_HOME", &dir);
+        let config = SimConfig {
+            logfireprob: -6.5,
+            ..SimConfig::default()
+        };
+        save_prefs(&config);
+        assert_eq!(load_prefs().unwrap().logfireprob, -6.5);
+
+        // prefs older than forestfire.toml give way to it
+        let prefs = std::fs::File::options()
+            .write(true)
+            .open(prefs_path().unwrap())
+            .unwrap();
+        prefs.set_modified(std::time::UNIX_EPOCH).unwrap();
+        assert!(load_prefs().is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+use macroquad::prelude::*;
+
+use crate::CellField;
+
+const MAX_UNDO: usize = 20;
+
+// the cells one edit changed, with the values they had before it
+pub struct UndoOp {
+    pub cells_before: Vec<(usize, usize, bool)>,
+}
+
+impl UndoOp {
+    // every cell that differs between before and after
+    pub fn between(before: &CellField, after: &CellField) -> UndoOp {
+        let changed = before ^ after;
+        UndoOp {
+            cells_before: changed
+                .iter_set()
+                .map(|(x, y)| (x, y, before.get(x, y)))
+                .collect(),
+        }
+    }
+}
+
+// edits made with the mouse, the simulation's own changes are not undone
+pub struct UndoStack {
+    pub ops: Vec<UndoOp>,
+    pub redo: Vec<UndoOp>,
+}
+
+impl UndoStack {
+    pub fn new() -> UndoStack {
+        UndoStack {
+            ops: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+}
+
+// a new edit drops whatever could be redone
+pub fn push_undo(op: UndoOp, stack: &mut UndoStack) {
+    if op.cells_before.is_empty() {
+        return;
+    }
+    if stack.ops.len() == MAX_UNDO {
+        stack.ops.remove(0);
+    }
+    stack.ops.push(op);
+    stack.redo.clear();
+}
+
+// puts the cells of op back and returns the op that reverts that
+fn swap_cells(op: UndoOp, cf: &mut CellField, img: &mut Image, alive: Color) -> UndoOp {
+    let mut inverse = Vec::with_capacity(op.cells_before.len());
+    for (x, y, set) in op.cells_before {
+        inverse.push((x, y, cf.get(x, y)));
+        let c = match set {
+            false => {
+                cf.clr(x, y);
+                BLACK
+            }
+            true => {
+                cf.set(x, y);
+                alive
+            }
+        };
+        img.set_pixel(x as u32, y as u32, c);
+    }
+    UndoOp {
+        cells_before: inverse,
+    }
+}
+
+// the apply functions return whether anything changed
+pub fn apply_undo(
+    stack: &mut UndoStack,
+    cf: &mut CellField,
+    img: &mut Image,
+    alive: Color,
+) -> bool {
+    let Some(op) = stack.ops.pop() else {
+        return false;
+    };
+    stack.redo.push(swap_cells(op, cf, img, alive));
+    true
+}
+
+pub fn apply_redo(
+    stack: &mut UndoStack,
+    cf: &mut CellField,
+    img: &mut Image,
+    alive: Color,
+) -> bool {
+    let Some(op) = stack.redo.pop() else {
+        return false;
+    };
+    stack.ops.push(swap_cells(op, cf, img, alive));
+    true
+}